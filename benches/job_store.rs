@@ -0,0 +1,101 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use ploy_engine::actors::{
+    job_store::JobStore,
+    job_worker_actor::{JobItem, JobOwner, JobStatus},
+};
+
+const JOBS: usize = 100_000;
+const JOB_NAMES: usize = 10;
+
+fn job(i: usize) -> JobItem {
+    JobItem::new(
+        format!("job-{i}"),
+        "{}".to_string(),
+        format!("job-name-{}", i % JOB_NAMES),
+        None,
+        None,
+        JobOwner {
+            process_id: format!("process-{}", i / 10),
+            root_process_id: format!("process-{}", i / 10),
+            step_id: "step".to_string(),
+        },
+    )
+}
+
+/// Jobs kept in a list as done before the store, every lookup scans it.
+fn linear_jobs() -> Vec<JobItem> {
+    (0..JOBS).map(job).collect()
+}
+
+fn linear_fetch(jobs: &mut [JobItem], job_name: &str) -> Vec<JobItem> {
+    let items = jobs
+        .iter_mut()
+        .filter(|job| job.status == JobStatus::Open && job.job_name == job_name)
+        .take(2)
+        .map(|job| {
+            job.status = JobStatus::InProgress;
+            job.clone()
+        })
+        .collect::<Vec<JobItem>>();
+
+    items
+}
+
+fn linear_complete(jobs: &mut [JobItem], id: &str) {
+    let job = jobs.iter_mut().find(|job| job.id == id).unwrap();
+    job.status = JobStatus::Completed;
+}
+
+fn job_store() -> JobStore {
+    let mut store = JobStore::default();
+    (0..JOBS).for_each(|i| store.insert(job(i)));
+    store
+}
+
+fn job_queue(c: &mut Criterion) {
+    let mut group = c.benchmark_group("job_queue");
+    group.sample_size(10);
+
+    // Jobs fetched towards the end of the queue, as the ones before are in progress
+    let last_id = format!("job-{}", JOBS - 1);
+    let last_name = format!("job-name-{}", (JOBS - 1) % JOB_NAMES);
+
+    group.bench_function("linear_fetch_and_complete", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut jobs = linear_jobs();
+                jobs[..JOBS - 1]
+                    .iter_mut()
+                    .for_each(|job| job.status = JobStatus::InProgress);
+                jobs
+            },
+            |jobs| {
+                linear_fetch(jobs, black_box(&last_name));
+                linear_complete(jobs, black_box(&last_id));
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("store_fetch_and_complete", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut store = job_store();
+                store.fetch(&[], JOBS - 1);
+                store
+            },
+            |store| {
+                store.fetch(black_box(std::slice::from_ref(&last_name)), 2);
+                store
+                    .finish(black_box(&last_id), Some(1), JobStatus::Completed, None)
+                    .unwrap();
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, job_queue);
+criterion_main!(benches);
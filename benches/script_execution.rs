@@ -0,0 +1,53 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use ploy_engine::steps::{
+    interpreter_pool::execute_script,
+    ploy_module::ScriptContext,
+    sandbox::SandboxPolicy,
+    script::{create_interpreter, ScriptModule, ScriptSource},
+};
+use rustpython::vm::py_serde::{deserialize, serialize};
+use serde_json::{json, value::Serializer, Value};
+
+const SCRIPT: &str = "to-upper";
+
+/// Execution as done before interpreters were pooled, with a new interpreter on every run.
+fn execute_with_new_interpreter(input: Value) -> Value {
+    let interpreter = create_interpreter();
+
+    interpreter.enter(|vm| {
+        let module = vm.import(SCRIPT, None, 0).unwrap();
+        let execute_fn = module.get_attr("execute", vm).unwrap();
+        let result = execute_fn
+            .call((deserialize(vm, input).unwrap(),), vm)
+            .unwrap();
+
+        serialize(vm, &result, Serializer).unwrap()
+    })
+}
+
+fn script_execution(c: &mut Criterion) {
+    let input = json!({ "name": "Hello world" });
+
+    let mut group = c.benchmark_group("script_execution");
+    group.throughput(Throughput::Elements(1));
+    group.sample_size(10);
+
+    group.bench_function("new_interpreter", |b| {
+        b.iter(|| execute_with_new_interpreter(black_box(input.clone())))
+    });
+
+    let script = ScriptSource::Module(ScriptModule::new(SCRIPT.to_string(), None));
+    let policy = SandboxPolicy::default();
+    let context = ScriptContext::default();
+
+    group.bench_function("pooled_interpreter", |b| {
+        b.iter(|| {
+            execute_script(&script, black_box(input.clone()), None, &policy, &context).unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, script_execution);
+criterion_main!(benches);
@@ -0,0 +1,87 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use ploy_engine::definition::{
+    parser::parse_xml,
+    schema_registry::{SchemaRegistry, SCHEMAS_PATH},
+};
+use serde_json::{json, Value};
+
+const SCHEMA_NAME: &str = "MessageSchema";
+
+const PROCESS_DEFINITION: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Ploy>
+    <Nodes>
+        <StartNode id="start" />
+
+        <ActivityNode id="activity1" job="TestJob" input="MessageSchema" output="MessageSchema">
+            <Inputs>
+                <Input name="message" from="start" output="message" />
+            </Inputs>
+        </ActivityNode>
+
+        <EndNode id="end">
+            <Inputs>
+                <Input name="message" from="activity1" output="message" />
+            </Inputs>
+        </EndNode>
+    </Nodes>
+    <Flow>
+        <FlowNode from="start" to="activity1"></FlowNode>
+        <FlowNode from="activity1" to="end"></FlowNode>
+    </Flow>
+</Ploy>"#;
+
+/// Validation as done before schemas were precompiled, reading the schema on every use.
+fn validate_from_disk(value: &Value) {
+    let schema_contents =
+        std::fs::read_to_string(format!("{SCHEMAS_PATH}/{SCHEMA_NAME}.json")).unwrap();
+    let schema: Value = serde_json::from_str(&schema_contents).unwrap();
+    let compiled = jsonschema::JSONSchema::compile(&schema).unwrap();
+
+    assert!(compiled.is_valid(value));
+}
+
+/// Each iteration validates the inputs and outputs of one step.
+fn step_validation(c: &mut Criterion) {
+    let schema_registry = SchemaRegistry::load(SCHEMAS_PATH).unwrap();
+
+    let mut process_definition = parse_xml("Bench", PROCESS_DEFINITION).unwrap();
+    process_definition.compile_schemas(&schema_registry);
+
+    let inputs = json!({ "message": "Hello" });
+    let outputs = json!({ "message": "HELLO" });
+
+    let mut group = c.benchmark_group("step_validation");
+    group.throughput(Throughput::Elements(1));
+
+    group.bench_function("read_and_compile", |b| {
+        b.iter(|| {
+            validate_from_disk(black_box(&inputs));
+            validate_from_disk(black_box(&outputs));
+        })
+    });
+
+    group.bench_function("schema_registry", |b| {
+        b.iter(|| {
+            schema_registry
+                .validate(black_box(&inputs), SCHEMA_NAME)
+                .unwrap();
+            schema_registry
+                .validate(black_box(&outputs), SCHEMA_NAME)
+                .unwrap();
+        })
+    });
+
+    group.bench_function("precompiled", |b| {
+        b.iter(|| {
+            let compiled = process_definition.get_compiled_schema(SCHEMA_NAME).unwrap();
+
+            SchemaRegistry::validate_compiled(compiled, black_box(&inputs)).unwrap();
+            SchemaRegistry::validate_compiled(compiled, black_box(&outputs)).unwrap();
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, step_validation);
+criterion_main!(benches);
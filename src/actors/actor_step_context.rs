@@ -1,12 +1,18 @@
-use actix::Addr;
+use std::time::Duration;
+
+use actix::{Addr, Recipient};
 use serde_json::{Map, Value};
 use uuid::Uuid;
 
-use crate::definition::step::ManageStep;
+use crate::{
+    definition::step::{JobRequest, ManageStep, ProcessReference},
+    steps::{ploy_module::ScriptContext, sandbox::SandboxOverrides, script::ScriptSource},
+};
 
 use super::{
     engine_actor::{EngineActor, StartProcessMessage},
-    job_worker_actor::{JobItem, JobWorkerActor},
+    job_worker_actor::{JobItem, JobMetadata, JobOwner, JobWorkerActor},
+    script_worker_actor::{ExecuteScriptMessage, ScriptCompletedMessage, ScriptWorkerActor},
 };
 
 #[derive(Debug, Clone)]
@@ -14,7 +20,13 @@ pub struct ActorStepContext {
     process_id: String,
     engine: Addr<EngineActor>,
     job_worker: Addr<JobWorkerActor>,
+    script_worker: Addr<ScriptWorkerActor>,
+    script_results: Recipient<ScriptCompletedMessage>,
+    script_context: ScriptContext,
     inputs: Map<String, Value>,
+    /// Priority of the process
+    priority: i32,
+    root_process_id: String,
 }
 
 impl ActorStepContext {
@@ -22,31 +34,65 @@ impl ActorStepContext {
         process_id: String,
         engine: Addr<EngineActor>,
         job_worker: Addr<JobWorkerActor>,
+        script_worker: Addr<ScriptWorkerActor>,
+        script_results: Recipient<ScriptCompletedMessage>,
+        script_context: ScriptContext,
         inputs: Map<String, Value>,
     ) -> Self {
         ActorStepContext {
+            root_process_id: process_id.clone(),
             process_id,
             engine,
             job_worker,
+            script_worker,
+            script_results,
+            script_context,
             inputs,
+            priority: 0,
         }
     }
 
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_root_process_id(mut self, root_process_id: String) -> Self {
+        self.root_process_id = root_process_id;
+        self
+    }
+
     pub fn job_worker(&self) -> Addr<JobWorkerActor> {
         self.job_worker.clone()
     }
 }
 
 impl ManageStep for ActorStepContext {
-    fn add_job(&self, job_name: String) -> String {
+    fn add_job(&self, request: JobRequest) -> String {
         let id = Uuid::new_v4().to_string();
 
         self.job_worker
-            .do_send(crate::actors::job_worker_actor::AddWorkItem(JobItem::new(
-                id.clone(),
-                Value::Object(self.get_inputs().clone()).to_string(),
-                job_name,
-            )));
+            .do_send(crate::actors::job_worker_actor::AddWorkItem(
+                JobItem::new(
+                    id.clone(),
+                    Value::Object(self.get_inputs().clone()).to_string(),
+                    request.job.name,
+                    request.job.version,
+                    request.output_schema,
+                    JobOwner {
+                        process_id: self.process_id.clone(),
+                        root_process_id: self.root_process_id.clone(),
+                        step_id: self.script_context.step_id.clone(),
+                    },
+                )
+                .with_priority(request.priority.unwrap_or(self.priority))
+                .with_metadata(JobMetadata {
+                    activity_name: request.activity_name,
+                    definition_name: self.script_context.definition_name.clone(),
+                    definition_version: self.script_context.definition_version.clone(),
+                    headers: request.headers,
+                }),
+            ));
 
         id
     }
@@ -57,7 +103,7 @@ impl ManageStep for ActorStepContext {
 
     fn start_process(
         &self,
-        process_name: String,
+        process: ProcessReference,
         inputs: Map<String, Value>,
     ) -> anyhow::Result<crate::definition::step::JobId> {
         let id = Uuid::new_v4().to_string();
@@ -65,10 +111,36 @@ impl ManageStep for ActorStepContext {
         self.engine.do_send(StartProcessMessage {
             root_process_id: Some(self.process_id.clone()),
             job_id: Some(id.clone()),
-            process_name,
+            process_name: process.name,
+            version: process.version,
             inputs,
+            skip_validation: false,
+            priority: self.priority,
         });
 
         Ok(id)
     }
+
+    fn run_script(
+        &self,
+        script: ScriptSource,
+        timeout: Duration,
+        sandbox: SandboxOverrides,
+        pure: bool,
+    ) -> crate::definition::step::JobId {
+        let id = Uuid::new_v4().to_string();
+
+        self.script_worker.do_send(ExecuteScriptMessage {
+            job_id: id.clone(),
+            script,
+            inputs: self.get_inputs().clone(),
+            timeout,
+            sandbox,
+            pure,
+            context: self.script_context.clone(),
+            reply_to: self.script_results.clone(),
+        });
+
+        id
+    }
 }
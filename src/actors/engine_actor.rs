@@ -1,19 +1,31 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, fmt, sync::Arc};
 
 use actix::{Actor, Addr, ArbiterHandle, AsyncContext, Handler, Message};
 use anyhow::{anyhow, Result};
-use log::info;
+use log::{info, warn};
+use semver::VersionReq;
 use serde_json::{Map, Value};
 
 use crate::{
-    actors::job_worker_actor::JobCompletedMessage,
-    definition::process_definition::ProcessDefinition,
+    actors::job_worker_actor::{JobCompletedMessage, JobFailedMessage},
+    definition::{
+        dependency_graph::DependencyGraph,
+        job_catalog::JobCatalog,
+        linter::{LintConfig, LintWarning, ProcessLinter},
+        process_definition::ProcessDefinition,
+        proto_contracts,
+        schema_registry::SchemaRegistry,
+        step::StepError,
+        validator::ValidationError,
+    },
+    steps::sandbox::SandboxPolicy,
 };
 
 use super::{
     job_worker_actor::JobWorkerActor,
     process_actor::ProcessActor,
-    process_context::{ProcessContext, ProcessState},
+    process_context::{ProcessContext, ProcessError, ProcessState},
+    script_worker_actor::ScriptWorkerActor,
 };
 
 #[derive(Message)]
@@ -22,7 +34,12 @@ pub struct StartProcessMessage {
     pub root_process_id: Option<String>,
     pub job_id: Option<String>,
     pub process_name: String,
+    /// Version requirement the deployed definition has to match, set by call steps
+    pub version: Option<String>,
     pub inputs: Map<String, Value>,
+    pub skip_validation: bool,
+    /// Inherited by the jobs of the process
+    pub priority: i32,
 }
 
 #[derive(Message)]
@@ -32,11 +49,45 @@ pub struct GetProcessMessage {
 }
 
 #[derive(Message)]
-#[rtype(result = "anyhow::Result<bool>")]
+#[rtype(result = "anyhow::Result<Vec<ValidationError>>")]
 pub struct ValidateProcessMessage {
     pub process_name: String,
 }
 
+#[derive(Message)]
+#[rtype(result = "anyhow::Result<Vec<LintWarning>>")]
+pub struct LintProcessMessage {
+    pub process_name: String,
+    pub config: LintConfig,
+}
+
+#[derive(Message)]
+#[rtype(result = "anyhow::Result<Vec<ValidationError>>")]
+pub struct DeployProcessMessage {
+    pub process_name: String,
+    pub process_definition: ProcessDefinition,
+    pub source: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "anyhow::Result<DependencyGraph>")]
+pub struct GetDependencyGraphMessage;
+
+#[derive(Message)]
+#[rtype(result = "anyhow::Result<()>")]
+pub struct RegisterSchemaMessage {
+    pub name: String,
+    pub version: semver::Version,
+    pub schema: Value,
+}
+
+#[derive(Message)]
+#[rtype(result = "anyhow::Result<Vec<String>>")]
+pub struct RegisterDescriptorSetMessage {
+    pub name: String,
+    pub descriptor_set: Vec<u8>,
+}
+
 #[derive(Message)]
 #[rtype(result = "anyhow::Result<()>")]
 pub struct EndProcessMessage {
@@ -44,17 +95,107 @@ pub struct EndProcessMessage {
     pub outputs: Map<String, Value>,
 }
 
+#[derive(Message)]
+#[rtype(result = "anyhow::Result<()>")]
+pub struct FailProcessMessage {
+    pub process_id: String,
+    pub step_id: String,
+    pub error: StepError,
+}
+
+pub const PROCESS_DEFINITIONS_PATH: &str = "data";
+
+/// Returned when starting a process whose definition did not pass validation.
+#[derive(Debug)]
+pub struct InvalidProcessDefinitionError {
+    pub process_name: String,
+    pub errors: Vec<ValidationError>,
+}
+
+impl fmt::Display for InvalidProcessDefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Process definition {} is invalid: {}",
+            self.process_name,
+            self.errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>()
+                .join("; ")
+        )
+    }
+}
+
+impl std::error::Error for InvalidProcessDefinitionError {}
+
+/// Returned for process names that are not a plain file name in the definitions directory.
+#[derive(Debug)]
+pub struct InvalidProcessNameError {
+    pub process_name: String,
+}
+
+impl fmt::Display for InvalidProcessNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid process name '{}'", self.process_name)
+    }
+}
+
+impl std::error::Error for InvalidProcessNameError {}
+
+/// File of the process definition, rejecting names that would point outside the definitions
+/// directory.
+fn process_definition_file(process_name: &str) -> Result<String> {
+    if process_name.is_empty() || process_name.contains(['/', '\\']) || process_name.contains("..")
+    {
+        return Err(InvalidProcessNameError {
+            process_name: process_name.to_string(),
+        }
+        .into());
+    }
+
+    Ok(format!("{PROCESS_DEFINITIONS_PATH}/{process_name}.ploy"))
+}
+
+struct DeployedProcessDefinition {
+    definition: Arc<ProcessDefinition>,
+    // Validated on import and deploy, reset when a called process is redeployed
+    validation_errors: Option<Vec<ValidationError>>,
+}
+
+impl DeployedProcessDefinition {
+    fn new(definition: Arc<ProcessDefinition>) -> Self {
+        Self {
+            definition,
+            validation_errors: None,
+        }
+    }
+}
+
 pub struct EngineActor {
     arbiter: ArbiterHandle,
     processes: HashMap<String, ProcessContext>,
     // sub_process_id -> (job_id, root_process_id)
     pending_job: HashMap<String, (String, String)>,
-    process_definitions: HashMap<String, Arc<ProcessDefinition>>,
+    process_definitions: HashMap<String, DeployedProcessDefinition>,
+    // Calls between the loaded process definitions
+    dependency_graph: DependencyGraph,
     job_worker: Addr<JobWorkerActor>,
+    script_worker: Addr<ScriptWorkerActor>,
+    job_catalog: Arc<JobCatalog>,
+    schema_registry: Arc<SchemaRegistry>,
+    sandbox_policy: Arc<SandboxPolicy>,
 }
 
 impl EngineActor {
-    pub fn new(arbiter: ArbiterHandle, job_worker: Addr<JobWorkerActor>) -> Self {
+    pub fn new(
+        arbiter: ArbiterHandle,
+        job_worker: Addr<JobWorkerActor>,
+        script_worker: Addr<ScriptWorkerActor>,
+        job_catalog: Arc<JobCatalog>,
+        schema_registry: Arc<SchemaRegistry>,
+        sandbox_policy: Arc<SandboxPolicy>,
+    ) -> Self {
         let processes = HashMap::default();
         let process_definitions = HashMap::default();
         let pending_job = HashMap::default();
@@ -63,8 +204,13 @@ impl EngineActor {
             arbiter,
             processes,
             job_worker,
+            script_worker,
+            job_catalog,
+            schema_registry,
+            sandbox_policy,
             pending_job,
             process_definitions,
+            dependency_graph: DependencyGraph::default(),
         }
     }
 
@@ -72,27 +218,51 @@ impl EngineActor {
         &mut self,
         process_name: &str,
         process_inputs: Map<String, Value>,
+        skip_validation: bool,
+        priority: i32,
+        root_process_id: Option<String>,
         ctx: &mut actix::Context<Self>,
     ) -> Result<String> {
         let process_definition = self.get_or_import_process_definition(process_name)?;
 
+        if !skip_validation {
+            let validation_errors = self.validate_process_definition(process_name)?;
+
+            if !validation_errors.is_empty() {
+                return Err(InvalidProcessDefinitionError {
+                    process_name: process_name.to_string(),
+                    errors: validation_errors,
+                }
+                .into());
+            }
+        }
+
         let job_worker_actor = self.job_worker.clone();
+        let script_worker_actor = self.script_worker.clone();
+        let schema_registry = self.schema_registry.clone();
 
         let my_addr = ctx.address();
 
         let process_id = uuid::Uuid::new_v4().to_string();
         let process_id_mv = process_id.clone();
-        let process_actor_addr = ProcessActor::start_in_arbiter(&self.arbiter, |_ctx| {
+        let root_process_id = root_process_id.unwrap_or_else(|| process_id.clone());
+        let root_process_id_mv = root_process_id.clone();
+        let process_actor_addr = ProcessActor::start_in_arbiter(&self.arbiter, move |_ctx| {
             ProcessActor::new(
                 process_id_mv,
                 my_addr,
                 job_worker_actor,
+                script_worker_actor,
+                schema_registry,
                 process_definition,
                 process_inputs,
             )
+            .with_priority(priority)
+            .with_root_process_id(root_process_id_mv)
         });
 
-        let process_context = ProcessContext::new(process_id.clone(), process_actor_addr);
+        let process_context =
+            ProcessContext::new(process_id.clone(), root_process_id, process_actor_addr);
 
         info!("Process started: {:#?}", process_context);
 
@@ -101,6 +271,24 @@ impl EngineActor {
         Ok(process_id)
     }
 
+    /// Checks that the deployed definition of the process matches the version requirement.
+    fn check_process_version(&mut self, process_name: &str, version: &str) -> Result<()> {
+        let process_definition = self.get_or_import_process_definition(process_name)?;
+        let requirement = VersionReq::parse(version)
+            .map_err(|e| anyhow!("Invalid version requirement '{}': {}", version, e))?;
+
+        if !requirement.matches(process_definition.get_version()) {
+            return Err(anyhow!(
+                "Process {} {} is required, but version {} is deployed",
+                process_name,
+                requirement,
+                process_definition.get_version()
+            ));
+        }
+
+        Ok(())
+    }
+
     pub fn get_process(&self, process_id: &str) -> Result<&ProcessContext> {
         self.processes
             .get(process_id)
@@ -113,37 +301,231 @@ impl EngineActor {
             .ok_or_else(|| anyhow!("Process not found"))
     }
 
+    /// Returns the loaded process definition, importing and validating it first if it is not
+    /// loaded yet.
     pub fn get_or_import_process_definition(
         &mut self,
         process_name: &str,
     ) -> Result<Arc<ProcessDefinition>> {
-        let process_definition = self.process_definitions.get(process_name);
+        if let Some(deployed) = self.process_definitions.get(process_name) {
+            return Ok(deployed.definition.clone());
+        }
 
-        if process_definition.is_none() {
-            let process_definition = Self::import_process_definition(process_name)?;
-            self.process_definitions
-                .insert(process_name.to_string(), process_definition.clone());
+        let process_definition = self.import_process_definition(process_name)?;
+        self.dependency_graph.insert(&process_definition);
+        self.invalidate_dependents(process_name);
+        self.validate_process_definition(process_name)?;
 
-            return Ok(process_definition);
-        }
+        Ok(process_definition)
+    }
 
-        Ok(process_definition
-            .expect("Process definition is present")
-            .clone())
+    /// Processes calling the given one are validated again on their next use.
+    fn invalidate_dependents(&mut self, process_name: &str) {
+        for dependent in self.dependency_graph.get_dependents(process_name) {
+            if let Some(deployed) = self.process_definitions.get_mut(&dependent) {
+                deployed.validation_errors = None;
+            }
+        }
     }
 
     pub fn get_process_definition(&self, process_name: &str) -> Result<Arc<ProcessDefinition>> {
         self.process_definitions
             .get(process_name)
-            .cloned()
+            .map(|deployed| deployed.definition.clone())
             .ok_or_else(|| anyhow!("Process definition not found"))
     }
 
-    fn import_process_definition(process_name: &str) -> Result<Arc<ProcessDefinition>> {
-        let file_name = format!("data/{process_name}.ploy");
+    /// Returns the cached validation errors of the process definition, validating it first if
+    /// it was not validated since it has been imported or deployed.
+    pub fn validate_process_definition(
+        &mut self,
+        process_name: &str,
+    ) -> Result<Vec<ValidationError>> {
+        let process_definition = self.get_or_import_process_definition(process_name)?;
+
+        if let Some(validation_errors) = self
+            .process_definitions
+            .get(process_name)
+            .and_then(|deployed| deployed.validation_errors.clone())
+        {
+            return Ok(validation_errors);
+        }
+
+        let validation_errors =
+            self.check_process_definition(process_name, process_definition, &self.dependency_graph);
+
+        if let Some(deployed) = self.process_definitions.get_mut(process_name) {
+            deployed.validation_errors = Some(validation_errors.clone());
+        }
+
+        Ok(validation_errors)
+    }
+
+    fn check_process_definition(
+        &self,
+        process_name: &str,
+        process_definition: Arc<ProcessDefinition>,
+        dependency_graph: &DependencyGraph,
+    ) -> Vec<ValidationError> {
+        let mut validation_errors = crate::definition::validator::ProcessValidator::validate(
+            process_definition,
+            self.job_catalog.clone(),
+            self.schema_registry.clone(),
+            self.sandbox_policy.clone(),
+        );
+        validation_errors.extend(dependency_graph.validate(process_name));
+
+        validation_errors
+    }
+
+    /// Stores the process definition in the definitions directory and replaces the loaded one,
+    /// unless it has validation errors. Processes calling it are validated again on their next
+    /// use.
+    pub fn deploy_process_definition(
+        &mut self,
+        process_name: &str,
+        mut process_definition: ProcessDefinition,
+        source: &str,
+    ) -> Result<Vec<ValidationError>> {
+        let file_name = process_definition_file(process_name)?;
+
+        process_definition.compile_schemas(&self.schema_registry);
+
+        let process_definition = Arc::new(process_definition);
+
+        // Validated against the calls as they will be once it is deployed
+        let mut dependency_graph = self.dependency_graph.clone();
+        dependency_graph.insert(&process_definition);
+
+        let validation_errors = self.check_process_definition(
+            process_name,
+            process_definition.clone(),
+            &dependency_graph,
+        );
+
+        if !validation_errors.is_empty() {
+            warn!(
+                "Process definition {} is invalid, not deployed",
+                process_name
+            );
+            return Ok(validation_errors);
+        }
+
+        std::fs::write(file_name, source)?;
+
+        self.process_definitions.insert(
+            process_name.to_string(),
+            DeployedProcessDefinition {
+                definition: process_definition,
+                validation_errors: Some(validation_errors.clone()),
+            },
+        );
+
+        self.dependency_graph = dependency_graph;
+        self.invalidate_dependents(process_name);
+
+        info!("Process definition deployed: {}", process_name);
+
+        Ok(validation_errors)
+    }
+
+    /// Imports every process definition found in the definitions directory that is not loaded
+    /// yet, so the dependency graph covers all known definitions. They are validated once all
+    /// of them are loaded. Done when the engine starts.
+    fn import_all_process_definitions(&mut self) -> Result<()> {
+        let mut imported = Vec::default();
+
+        for entry in std::fs::read_dir(PROCESS_DEFINITIONS_PATH)? {
+            let path = entry?.path();
+
+            if path.extension().and_then(|extension| extension.to_str()) != Some("ploy") {
+                continue;
+            }
+
+            let Some(process_name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+
+            if self.process_definitions.contains_key(process_name) {
+                continue;
+            }
+
+            match self.import_process_definition(process_name) {
+                Ok(_) => imported.push(process_name.to_string()),
+                Err(err) => warn!(
+                    "Failed to import process definition {}: {}",
+                    process_name, err
+                ),
+            }
+        }
+
+        if imported.is_empty() {
+            return Ok(());
+        }
+
+        self.dependency_graph = DependencyGraph::build(
+            self.process_definitions
+                .values()
+                .map(|deployed| deployed.definition.as_ref()),
+        );
+
+        for process_name in imported {
+            self.validate_process_definition(&process_name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Imports and validates the loaded definitions referring to any of the schemas again, so
+    /// their schemas are compiled against the registry as it is now. Running processes keep
+    /// the definitions they were started with.
+    fn reimport_process_definitions(&mut self, schema_names: &[String]) -> Result<()> {
+        let mut process_names = Vec::default();
+
+        for (process_name, deployed) in &self.process_definitions {
+            for reference in deployed.definition.get_schema_references() {
+                if self.schema_registry.refers_to(&reference, schema_names)? {
+                    process_names.push(process_name.clone());
+                    break;
+                }
+            }
+        }
+
+        for process_name in process_names {
+            match self.import_process_definition(&process_name) {
+                Ok(process_definition) => self.dependency_graph.insert(&process_definition),
+                Err(err) => {
+                    warn!(
+                        "Failed to import process definition {}: {}",
+                        process_name, err
+                    );
+                    self.process_definitions.remove(&process_name);
+                    self.dependency_graph.remove(&process_name);
+                    continue;
+                }
+            }
+
+            self.validate_process_definition(&process_name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads the process definition from the definitions directory, without validating it or
+    /// adding it to the dependency graph.
+    fn import_process_definition(&mut self, process_name: &str) -> Result<Arc<ProcessDefinition>> {
+        let file_name = process_definition_file(process_name)?;
         let file_contents = std::fs::read_to_string(file_name)?;
 
-        let process_definition = Arc::new(crate::definition::parser::parse_xml(&file_contents)?);
+        let mut process_definition =
+            crate::definition::parser::parse_xml(process_name, &file_contents)?;
+        process_definition.compile_schemas(&self.schema_registry);
+
+        let process_definition = Arc::new(process_definition);
+        self.process_definitions.insert(
+            process_name.to_string(),
+            DeployedProcessDefinition::new(process_definition.clone()),
+        );
 
         Ok(process_definition)
     }
@@ -151,13 +533,37 @@ impl EngineActor {
 
 impl Actor for EngineActor {
     type Context = actix::Context<Self>;
+
+    fn started(&mut self, _ctx: &mut Self::Context) {
+        if let Err(err) = self.import_all_process_definitions() {
+            warn!("Failed to import process definitions: {}", err);
+        }
+    }
 }
 
 impl Handler<StartProcessMessage> for EngineActor {
     type Result = Result<String>;
 
     fn handle(&mut self, msg: StartProcessMessage, ctx: &mut Self::Context) -> Self::Result {
-        let process_id = self.start_process(&msg.process_name, msg.inputs, ctx)?;
+        // Called processes share the root of their caller
+        let root_process_id = msg
+            .root_process_id
+            .as_ref()
+            .and_then(|caller_id| self.processes.get(caller_id))
+            .map(|caller| caller.root_process_id.clone());
+
+        if let Some(version) = &msg.version {
+            self.check_process_version(&msg.process_name, version)?;
+        }
+
+        let process_id = self.start_process(
+            &msg.process_name,
+            msg.inputs,
+            msg.skip_validation,
+            msg.priority,
+            root_process_id,
+            ctx,
+        )?;
 
         if msg.job_id.is_some() && msg.root_process_id.is_some() {
             self.pending_job.insert(
@@ -214,6 +620,40 @@ impl Handler<EndProcessMessage> for EngineActor {
     }
 }
 
+impl Handler<FailProcessMessage> for EngineActor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: FailProcessMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let process = self.get_process_mut(&msg.process_id)?;
+
+        if let Some(process_addr) = process.process_addr.take() {
+            process_addr.do_send(crate::actors::process_actor::EndProcessMessage {});
+        }
+
+        process.state = ProcessState::Failed;
+        process.error = Some(ProcessError {
+            step_id: msg.step_id,
+            error: msg.error.clone(),
+        });
+
+        warn!("Process failed: {:#?}", process);
+
+        // The call step of the root process fails with the same error
+        if let Some((job_id, root_process_id)) = self.pending_job.remove(&msg.process_id) {
+            let root_process = self.get_process(&root_process_id)?;
+
+            if let Some(root_process_addr) = root_process.process_addr.as_ref() {
+                root_process_addr.do_send(JobFailedMessage {
+                    job_id,
+                    error: msg.error,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl Handler<GetProcessMessage> for EngineActor {
     type Result = Result<ProcessContext>;
 
@@ -223,13 +663,77 @@ impl Handler<GetProcessMessage> for EngineActor {
 }
 
 impl Handler<ValidateProcessMessage> for EngineActor {
-    type Result = Result<bool>;
+    type Result = Result<Vec<ValidationError>>;
 
     fn handle(&mut self, msg: ValidateProcessMessage, _ctx: &mut Self::Context) -> Self::Result {
-        let process_definition = Self::import_process_definition(&msg.process_name)?;
+        self.validate_process_definition(&msg.process_name)
+    }
+}
 
-        Ok(crate::definition::validator::ProcessValidator::validate(
-            process_definition,
+impl Handler<LintProcessMessage> for EngineActor {
+    type Result = Result<Vec<LintWarning>>;
+
+    fn handle(&mut self, msg: LintProcessMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let process_definition = self.get_or_import_process_definition(&msg.process_name)?;
+
+        Ok(ProcessLinter::lint(
+            &process_definition,
+            &self.dependency_graph,
+            &self.schema_registry,
+            &msg.config,
         ))
     }
 }
+
+impl Handler<RegisterSchemaMessage> for EngineActor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: RegisterSchemaMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.schema_registry
+            .register(&msg.name, msg.version, msg.schema)?;
+
+        // Schema references without a version may resolve differently now
+        self.reimport_process_definitions(&[msg.name])
+    }
+}
+
+impl Handler<RegisterDescriptorSetMessage> for EngineActor {
+    type Result = Result<Vec<String>>;
+
+    fn handle(
+        &mut self,
+        msg: RegisterDescriptorSetMessage,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        let registered = proto_contracts::save_descriptor_set(
+            &self.schema_registry,
+            &msg.name,
+            &msg.descriptor_set,
+        )?;
+
+        // Definitions referencing the new messages can be compiled now
+        self.reimport_process_definitions(&registered)?;
+
+        Ok(registered)
+    }
+}
+
+impl Handler<DeployProcessMessage> for EngineActor {
+    type Result = Result<Vec<ValidationError>>;
+
+    fn handle(&mut self, msg: DeployProcessMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.deploy_process_definition(&msg.process_name, msg.process_definition, &msg.source)
+    }
+}
+
+impl Handler<GetDependencyGraphMessage> for EngineActor {
+    type Result = Result<DependencyGraph>;
+
+    fn handle(
+        &mut self,
+        _msg: GetDependencyGraphMessage,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        Ok(self.dependency_graph.clone())
+    }
+}
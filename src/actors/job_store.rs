@@ -0,0 +1,492 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    env,
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use serde_json::json;
+
+use crate::util;
+
+use super::job_worker_actor::{
+    InvalidJobStateError, JobItem, JobNotFoundError, JobStatus, StaleJobAttemptError,
+};
+
+/// How long finished jobs are kept, e.g. `30m`.
+pub const JOB_RETENTION_VAR: &str = "PLOY_JOB_RETENTION";
+/// File finished jobs are appended to as JSON lines before they are deleted.
+pub const JOB_ARCHIVE_VAR: &str = "PLOY_JOB_ARCHIVE";
+/// How long a worker has to finish a job before it is handed out again, e.g. `5m`.
+pub const JOB_LEASE_VAR: &str = "PLOY_JOB_LEASE";
+
+const DEFAULT_RETENTION: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_LEASE: Duration = Duration::from_secs(5 * 60);
+/// Waiting this long makes up for one priority level, so low priority jobs are not starved.
+const PRIORITY_AGING: Duration = Duration::from_secs(10);
+/// Queue time between the jobs of one process, jobs other processes add meanwhile are queued
+/// between them instead of behind all of them.
+const PROCESS_JOB_SPACING: Duration = Duration::from_millis(100);
+
+/// Open jobs are handed out in the order of their schedule, then of the sequence.
+type QueueKey = (i64, u64, String);
+
+/// What happens to completed and failed jobs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobRetention {
+    /// Finished jobs are deleted once they are older
+    pub keep_for: Duration,
+    pub archive: Option<PathBuf>,
+}
+
+impl Default for JobRetention {
+    fn default() -> Self {
+        Self {
+            keep_for: DEFAULT_RETENTION,
+            archive: None,
+        }
+    }
+}
+
+impl JobRetention {
+    /// Retention set by the environment or the default.
+    pub fn from_env() -> Result<Self> {
+        let keep_for = match env::var(JOB_RETENTION_VAR) {
+            Ok(retention) => {
+                util::parse_duration(&retention).map_err(|e| anyhow!("{JOB_RETENTION_VAR}: {e}"))?
+            }
+            Err(_) => DEFAULT_RETENTION,
+        };
+
+        let retention = Self {
+            keep_for,
+            archive: env::var_os(JOB_ARCHIVE_VAR).map(PathBuf::from),
+        };
+
+        info!(
+            "Finished jobs are kept for {}s{}",
+            retention.keep_for.as_secs(),
+            retention
+                .archive
+                .as_ref()
+                .map(|archive| format!(", archived to {}", archive.display()))
+                .unwrap_or_default()
+        );
+
+        Ok(retention)
+    }
+}
+
+/// Job lease set by the environment or the default.
+pub fn lease_from_env() -> Result<Duration> {
+    let lease = match env::var(JOB_LEASE_VAR) {
+        Ok(lease) => util::parse_duration(&lease).map_err(|e| anyhow!("{JOB_LEASE_VAR}: {e}"))?,
+        Err(_) => DEFAULT_LEASE,
+    };
+
+    info!("Jobs are leased for {}s", lease.as_secs());
+
+    Ok(lease)
+}
+
+#[derive(Debug)]
+struct StoredJob {
+    item: JobItem,
+    /// Order the job was added in
+    sequence: u64,
+    /// Queue time in milliseconds, earlier by `PRIORITY_AGING` per priority level
+    schedule: i64,
+}
+
+impl StoredJob {
+    fn key(&self) -> QueueKey {
+        (self.schedule, self.sequence, self.item.id.clone())
+    }
+}
+
+/// Jobs indexed by id, status, job name and process. Open jobs are handed out by priority,
+/// jobs waiting longer and jobs of processes with fewer queued jobs first. Finished jobs are
+/// removed once the retention passed.
+#[derive(Debug)]
+pub struct JobStore {
+    jobs: HashMap<String, StoredJob>,
+    by_status: HashMap<JobStatus, BTreeSet<QueueKey>>,
+    /// Open jobs only, as workers fetch by job name
+    open_by_job_name: HashMap<String, BTreeSet<QueueKey>>,
+    by_process: HashMap<String, HashSet<String>>,
+    /// Queue time of the last job each process added
+    process_queue_times: HashMap<String, i64>,
+    /// Queue times are counted from here
+    epoch: Instant,
+    /// Finished jobs, oldest first
+    finished: VecDeque<(Instant, String)>,
+    next_sequence: u64,
+    retention: JobRetention,
+    lease: Duration,
+}
+
+impl Default for JobStore {
+    fn default() -> Self {
+        Self::new(JobRetention::default(), DEFAULT_LEASE)
+    }
+}
+
+impl JobStore {
+    pub fn new(retention: JobRetention, lease: Duration) -> Self {
+        Self {
+            jobs: HashMap::default(),
+            by_status: HashMap::default(),
+            open_by_job_name: HashMap::default(),
+            by_process: HashMap::default(),
+            process_queue_times: HashMap::default(),
+            epoch: Instant::now(),
+            finished: VecDeque::default(),
+            next_sequence: 0,
+            retention,
+            lease,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&JobItem> {
+        self.jobs.get(id).map(|job| &job.item)
+    }
+
+    /// Number of jobs with the status.
+    pub fn count(&self, status: &JobStatus) -> usize {
+        self.by_status.get(status).map_or(0, |jobs| jobs.len())
+    }
+
+    /// Jobs of a process, in no particular order.
+    pub fn process_jobs(&self, process_id: &str) -> Vec<&JobItem> {
+        self.by_process
+            .get(process_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.get(id))
+            .collect()
+    }
+
+    pub fn insert(&mut self, item: JobItem) {
+        if self.jobs.contains_key(&item.id) {
+            warn!("Job {} already exists and is not added again", item.id);
+            return;
+        }
+
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        let job = StoredJob {
+            schedule: self.schedule(&item),
+            item,
+            sequence,
+        };
+
+        self.index(&job);
+        self.by_process
+            .entry(job.item.owner.process_id.clone())
+            .or_default()
+            .insert(job.item.id.clone());
+        self.jobs.insert(job.item.id.clone(), job);
+    }
+
+    /// Queues the job after the previous job of its process, moved ahead by `PRIORITY_AGING`
+    /// per priority level.
+    fn schedule(&mut self, item: &JobItem) -> i64 {
+        let now = self.epoch.elapsed().as_millis() as i64;
+
+        let queue_time = self
+            .process_queue_times
+            .entry(item.owner.process_id.clone())
+            .or_insert(now);
+        *queue_time = now.max(*queue_time + PROCESS_JOB_SPACING.as_millis() as i64);
+
+        *queue_time - i64::from(item.priority) * PRIORITY_AGING.as_millis() as i64
+    }
+
+    fn index(&mut self, job: &StoredJob) {
+        let (item, key) = (&job.item, job.key());
+
+        if item.status == JobStatus::Open {
+            self.open_by_job_name
+                .entry(item.job_name.clone())
+                .or_default()
+                .insert(key.clone());
+        }
+
+        self.by_status
+            .entry(item.status.clone())
+            .or_default()
+            .insert(key);
+    }
+
+    fn unindex(&mut self, job: &StoredJob) {
+        let (item, key) = (&job.item, job.key());
+
+        if item.status == JobStatus::Open {
+            if let Some(jobs) = self.open_by_job_name.get_mut(&item.job_name) {
+                jobs.remove(&key);
+
+                if jobs.is_empty() {
+                    self.open_by_job_name.remove(&item.job_name);
+                }
+            }
+        }
+
+        if let Some(jobs) = self.by_status.get_mut(&item.status) {
+            jobs.remove(&key);
+        }
+    }
+
+    fn set_status(&mut self, id: &str, status: JobStatus) {
+        let Some(mut job) = self.jobs.remove(id) else {
+            return;
+        };
+
+        self.unindex(&job);
+        job.item.status = status;
+        self.index(&job);
+
+        self.jobs.insert(id.to_string(), job);
+    }
+
+    /// Hands out up to `limit` open jobs in queue order, of the given job names or of all jobs
+    /// when none are given. The jobs are in progress afterwards, until their lease ends.
+    pub fn fetch(&mut self, job_names: &[String], limit: usize) -> Vec<JobItem> {
+        let ids = if job_names.is_empty() {
+            self.by_status
+                .get(&JobStatus::Open)
+                .into_iter()
+                .flatten()
+                .take(limit)
+                .cloned()
+                .collect::<Vec<QueueKey>>()
+        } else {
+            let mut ids = job_names
+                .iter()
+                .filter_map(|job_name| self.open_by_job_name.get(job_name))
+                .flat_map(|jobs| jobs.iter().take(limit).cloned())
+                .collect::<Vec<QueueKey>>();
+
+            ids.sort();
+            ids.dedup();
+            ids.truncate(limit);
+            ids
+        };
+
+        ids.into_iter()
+            .filter_map(|(_, _, id)| {
+                self.set_status(&id, JobStatus::InProgress);
+
+                let job = self.jobs.get_mut(&id)?;
+                job.item.attempts += 1;
+                job.item.lease_deadline = Some(SystemTime::now() + self.lease);
+
+                Some(job.item.clone())
+            })
+            .collect()
+    }
+
+    /// Moves a job in progress to `status`, if `attempt` is missing or the attempt it was last
+    /// handed out in. Returns false when the job already has the status and was given the same
+    /// idempotency key, so the request is a repeat of a finished one.
+    pub fn finish(
+        &mut self,
+        id: &str,
+        attempt: Option<u32>,
+        status: JobStatus,
+        idempotency_key: Option<String>,
+    ) -> Result<bool> {
+        let item = self.get(id).ok_or_else(|| JobNotFoundError {
+            job_id: id.to_string(),
+        })?;
+
+        if let Some(attempt) = attempt.filter(|attempt| *attempt != item.attempts) {
+            return Err(StaleJobAttemptError {
+                job_id: id.to_string(),
+                attempt,
+                current_attempt: item.attempts,
+            }
+            .into());
+        }
+
+        if item.status == JobStatus::InProgress {
+            self.set_status(id, status);
+            self.jobs
+                .get_mut(id)
+                .expect("Job exists")
+                .item
+                .idempotency_key = idempotency_key;
+            self.finished.push_back((Instant::now(), id.to_string()));
+
+            return Ok(true);
+        }
+
+        if item.status == status
+            && idempotency_key.is_some()
+            && item.idempotency_key == idempotency_key
+        {
+            info!("Job {} is already {}, repeat ignored", id, status);
+
+            return Ok(false);
+        }
+
+        Err(InvalidJobStateError {
+            job_id: id.to_string(),
+            status: item.status.clone(),
+        }
+        .into())
+    }
+
+    /// Opens the jobs in progress whose lease ended, so they are handed out again in their
+    /// previous place. Returns their ids.
+    pub fn expire_leases(&mut self, now: SystemTime) -> Vec<String> {
+        let expired = self
+            .by_status
+            .get(&JobStatus::InProgress)
+            .into_iter()
+            .flatten()
+            .filter(|(_, _, id)| {
+                self.get(id)
+                    .and_then(|item| item.lease_deadline)
+                    .is_some_and(|deadline| deadline <= now)
+            })
+            .map(|(_, _, id)| id.clone())
+            .collect::<Vec<String>>();
+
+        for id in expired.iter() {
+            self.set_status(id, JobStatus::Open);
+
+            if let Some(job) = self.jobs.get_mut(id) {
+                job.item.lease_deadline = None;
+            }
+        }
+
+        expired
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<JobItem> {
+        let job = self.jobs.remove(id)?;
+
+        self.unindex(&job);
+
+        if let Some(jobs) = self.by_process.get_mut(&job.item.owner.process_id) {
+            jobs.remove(id);
+
+            if jobs.is_empty() {
+                self.by_process.remove(&job.item.owner.process_id);
+                self.process_queue_times.remove(&job.item.owner.process_id);
+            }
+        }
+
+        Some(job.item)
+    }
+
+    /// Removes the open jobs of a process, no one waits for their results anymore.
+    pub fn remove_open_process_jobs(&mut self, process_id: &str) -> usize {
+        let open = self
+            .process_jobs(process_id)
+            .into_iter()
+            .filter(|item| item.status == JobStatus::Open)
+            .map(|item| item.id.clone())
+            .collect::<Vec<String>>();
+
+        for id in open.iter() {
+            self.remove(id);
+        }
+
+        open.len()
+    }
+
+    /// Removes the jobs finished before the retention, archiving them first when an archive
+    /// is set. Jobs that could not be archived are kept until the next expiry. Returns the
+    /// number of removed jobs.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let mut expired = VecDeque::default();
+
+        while let Some((finished_at, id)) = self.finished.pop_front() {
+            if now.saturating_duration_since(finished_at) < self.retention.keep_for {
+                self.finished.push_front((finished_at, id));
+                break;
+            }
+
+            if self.jobs.contains_key(&id) {
+                expired.push_back((finished_at, id));
+            }
+        }
+
+        let mut archived = expired.len();
+
+        if let Some(archive) = &self.retention.archive {
+            if !expired.is_empty() {
+                let items = expired
+                    .iter()
+                    .filter_map(|(_, id)| self.get(id))
+                    .collect::<Vec<&JobItem>>();
+
+                archived = 0;
+
+                if let Err(err) = Self::archive(archive, &items, &mut archived) {
+                    warn!(
+                        "{} of {} jobs are not archived to {}: {}",
+                        items.len() - archived,
+                        items.len(),
+                        archive.display(),
+                        err
+                    );
+                }
+            }
+        }
+
+        for (_, id) in expired.drain(..archived) {
+            self.remove(&id);
+        }
+
+        while let Some(unarchived) = expired.pop_back() {
+            self.finished.push_front(unarchived);
+        }
+
+        archived
+    }
+
+    /// Appends the jobs to the archive in order, counting the ones written.
+    fn archive(archive: &PathBuf, items: &[&JobItem], archived: &mut usize) -> Result<()> {
+        let archived_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        let mut file = OpenOptions::new().create(true).append(true).open(archive)?;
+
+        for item in items {
+            let line = json!({
+                "id": item.id,
+                "jobName": item.job_name,
+                "jobVersion": item.job_version,
+                "inputs": item.inputs,
+                "status": item.status.to_string(),
+                "priority": item.priority,
+                "attempts": item.attempts,
+                "processId": item.owner.process_id,
+                "rootProcessId": item.owner.root_process_id,
+                "stepId": item.owner.step_id,
+                "archivedAt": archived_at,
+            });
+
+            writeln!(file, "{}", line)?;
+            *archived += 1;
+        }
+
+        Ok(())
+    }
+}
@@ -1,13 +1,131 @@
-use actix::{Actor, Handler, Message, Recipient};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use actix::{Actor, AsyncContext, Handler, Message, Recipient};
 use anyhow::Result;
-use log::info;
+use log::{info, warn};
 use serde_json::{Map, Value};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+use crate::definition::{
+    job_catalog::JobCatalog, schema_registry::SchemaRegistry, step::StepError,
+};
+
+use super::job_store::{JobRetention, JobStore};
+
+/// Jobs handed out per work request.
+const WORK_ITEMS_PER_REQUEST: usize = 2;
+/// How often finished jobs past their retention are removed and jobs past their lease are
+/// opened again.
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Jobs are fetched while `Open`, and completed or failed while `InProgress`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum JobStatus {
     Open,
     InProgress,
     Completed,
+    Failed,
+}
+
+impl fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Returned when completing or failing a job that does not exist.
+#[derive(Debug)]
+pub struct JobNotFoundError {
+    pub job_id: String,
+}
+
+impl fmt::Display for JobNotFoundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Job {} not found", self.job_id)
+    }
+}
+
+impl std::error::Error for JobNotFoundError {}
+
+/// Returned when completing or failing a job that is not in progress, unless it was finished
+/// the same way with the same idempotency key.
+#[derive(Debug)]
+pub struct InvalidJobStateError {
+    pub job_id: String,
+    pub status: JobStatus,
+}
+
+impl fmt::Display for InvalidJobStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            JobStatus::Open => write!(
+                f,
+                "Job {} was not fetched yet or its lease ended",
+                self.job_id
+            ),
+            _ => write!(f, "Job {} is already {}", self.job_id, self.status),
+        }
+    }
+}
+
+impl std::error::Error for InvalidJobStateError {}
+
+/// Returned when completing or failing an attempt of a job that was handed out again since.
+#[derive(Debug)]
+pub struct StaleJobAttemptError {
+    pub job_id: String,
+    pub attempt: u32,
+    pub current_attempt: u32,
+}
+
+impl fmt::Display for StaleJobAttemptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Attempt {} of job {} is stale, the current attempt is {}",
+            self.attempt, self.job_id, self.current_attempt
+        )
+    }
+}
+
+impl std::error::Error for StaleJobAttemptError {}
+
+/// Returned when completing a job with outputs that violate its contract or output schema.
+#[derive(Debug)]
+pub struct InvalidJobOutputsError {
+    pub job_id: String,
+    pub message: String,
+}
+
+impl fmt::Display for InvalidJobOutputsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for InvalidJobOutputsError {}
+
+/// Process step a job was added by, its completion is delivered to that process only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobOwner {
+    pub process_id: String,
+    /// Process started by a client, the owner itself unless it was called by another process
+    pub root_process_id: String,
+    pub step_id: String,
+}
+
+/// Where a job comes from, handed to the workers with the job.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JobMetadata {
+    pub activity_name: Option<String>,
+    pub definition_name: String,
+    pub definition_version: String,
+    /// Custom headers declared on the activity
+    pub headers: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone)]
@@ -15,18 +133,56 @@ pub struct JobItem {
     pub id: String,
     pub inputs: String,
     pub job_name: String,
+    pub job_version: Option<String>,
+    /// Output schema of the activity, completions are validated against it
+    pub output_schema: Option<String>,
+    pub owner: JobOwner,
+    pub metadata: JobMetadata,
+    /// Higher priority jobs are handed out first
+    pub priority: i32,
     pub status: JobStatus,
+    /// Number of times the job was handed out
+    pub attempts: u32,
+    /// The job is handed out again when it is still in progress after this
+    pub lease_deadline: Option<SystemTime>,
+    /// Idempotency key the job was completed or failed with
+    pub idempotency_key: Option<String>,
 }
 
 impl JobItem {
-    pub fn new(id: String, inputs: String, job_name: String) -> Self {
+    pub fn new(
+        id: String,
+        inputs: String,
+        job_name: String,
+        job_version: Option<String>,
+        output_schema: Option<String>,
+        owner: JobOwner,
+    ) -> Self {
         Self {
             id,
             inputs,
             job_name,
+            job_version,
+            output_schema,
+            owner,
+            metadata: JobMetadata::default(),
+            priority: 0,
             status: JobStatus::Open,
+            attempts: 0,
+            lease_deadline: None,
+            idempotency_key: None,
         }
     }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: JobMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
 }
 
 #[derive(Message)]
@@ -46,47 +202,171 @@ impl JobCompletedMessage {
     }
 }
 
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "Result<(), anyhow::Error>")]
+pub struct JobFailedMessage {
+    pub job_id: String,
+    pub error: StepError,
+}
+
+/// Completes a job in progress, sent by the workers.
+#[derive(Message)]
+#[rtype(result = "Result<()>")]
+pub struct CompleteWorkItem {
+    pub job_id: String,
+    /// Attempt the worker was handed the job in, not checked when missing
+    pub attempt: Option<u32>,
+    pub outputs: Map<String, Value>,
+    pub idempotency_key: Option<String>,
+}
+
+/// Fails a job in progress, sent by the workers.
+#[derive(Message)]
+#[rtype(result = "Result<()>")]
+pub struct FailWorkItem {
+    pub job_id: String,
+    /// Attempt the worker was handed the job in, not checked when missing
+    pub attempt: Option<u32>,
+    pub error: StepError,
+    pub idempotency_key: Option<String>,
+}
+
+/// Subscribes a process to the completions and failures of the jobs it owns.
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct AddCompletionSubscriber(pub Recipient<JobCompletedMessage>);
+pub struct AddCompletionSubscriber {
+    pub process_id: String,
+    pub completed: Recipient<JobCompletedMessage>,
+    pub failed: Recipient<JobFailedMessage>,
+}
+
+/// Sent when a process ends, completions of its remaining jobs are dropped.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RemoveCompletionSubscriber {
+    pub process_id: String,
+}
 
+#[derive(Debug)]
+pub struct JobSubscriber {
+    completed: Recipient<JobCompletedMessage>,
+    failed: Recipient<JobFailedMessage>,
+}
+
+/// Fetches open jobs, of the given job names or of all jobs when none are given.
 #[derive(Message)]
 #[rtype(result = "Vec<JobItem>")]
-pub struct GetWorkItems;
+pub struct GetWorkItems {
+    pub job_names: Vec<String>,
+}
 
 #[derive(Debug)]
 pub struct JobWorkerActor {
-    pub work_items: Vec<JobItem>,
-    pub completed_subscribers: Vec<Recipient<JobCompletedMessage>>,
+    pub jobs: JobStore,
+    /// process_id -> subscriber
+    pub subscribers: HashMap<String, JobSubscriber>,
+    job_catalog: Arc<JobCatalog>,
+    schema_registry: Arc<SchemaRegistry>,
 }
 
 impl JobWorkerActor {
-    pub fn new() -> Self {
+    pub fn new(
+        retention: JobRetention,
+        lease: Duration,
+        job_catalog: Arc<JobCatalog>,
+        schema_registry: Arc<SchemaRegistry>,
+    ) -> Self {
         JobWorkerActor {
-            work_items: Vec::new(),
-            completed_subscribers: Vec::new(),
+            jobs: JobStore::new(retention, lease),
+            subscribers: HashMap::new(),
+            job_catalog,
+            schema_registry,
         }
     }
 
-    fn set_in_progress(&mut self, id: &str) {
-        if let Some(work_item) = self
-            .work_items
-            .iter_mut()
-            .find(|work_item| work_item.id == id)
+    /// Checks the outputs against the contract of the job and the output schema of the
+    /// activity, which the process checks them against once they are accepted.
+    fn check_outputs(&self, job_item: &JobItem, outputs: &Map<String, Value>) -> Result<()> {
+        let invalid = |message: String| InvalidJobOutputsError {
+            job_id: job_item.id.clone(),
+            message,
+        };
+
+        match self
+            .job_catalog
+            .get(&job_item.job_name, job_item.job_version.as_deref())
         {
-            work_item.status = JobStatus::InProgress;
+            Ok(specification) => specification.validate_outputs(outputs).map_err(|errors| {
+                invalid(format!(
+                    "Outputs violate job {} {} contract: {}",
+                    specification.name,
+                    specification.version,
+                    errors.join("; ")
+                ))
+            })?,
+            Err(err) => warn!("Job {} outputs are not checked: {}", job_item.id, err),
         }
+
+        let Some(output_schema) = &job_item.output_schema else {
+            return Ok(());
+        };
+
+        let compiled = match self.schema_registry.get_compiled(output_schema) {
+            Ok(compiled) => compiled,
+            Err(err) => {
+                warn!("Job {} outputs are not checked: {}", job_item.id, err);
+                return Ok(());
+            }
+        };
+
+        SchemaRegistry::validate_compiled(&compiled, &Value::Object(outputs.clone())).map_err(
+            |err| {
+                invalid(format!(
+                    "Outputs violate output schema {}: {}",
+                    output_schema,
+                    err.to_string().lines().collect::<Vec<&str>>().join("; ")
+                ))
+                .into()
+            },
+        )
     }
-}
 
-impl Default for JobWorkerActor {
-    fn default() -> Self {
-        JobWorkerActor::new()
+    fn owner_subscriber(&self, job_id: &str) -> Option<&JobSubscriber> {
+        let owner = &self.jobs.get(job_id)?.owner;
+
+        let subscriber = self.subscribers.get(&owner.process_id);
+
+        if subscriber.is_none() {
+            warn!(
+                "Process {} of job {} has ended, its result is dropped",
+                owner.process_id, job_id
+            );
+        }
+
+        subscriber
     }
 }
 
 impl Actor for JobWorkerActor {
     type Context = actix::Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(EXPIRE_INTERVAL, |actor, _ctx| {
+            for job_id in actor.jobs.expire_leases(SystemTime::now()) {
+                warn!("Lease of job {} ended, it is handed out again", job_id);
+            }
+
+            let expired = actor.jobs.expire(Instant::now());
+
+            if expired > 0 {
+                info!(
+                    "Removed {} finished jobs, {} left",
+                    expired,
+                    actor.jobs.len()
+                );
+            }
+        });
+    }
 }
 
 impl Handler<AddWorkItem> for JobWorkerActor {
@@ -94,27 +374,15 @@ impl Handler<AddWorkItem> for JobWorkerActor {
 
     fn handle(&mut self, msg: AddWorkItem, _ctx: &mut Self::Context) -> Self::Result {
         info!("Adding work item: {:?}", msg.0);
-        self.work_items.push(msg.0);
+        self.jobs.insert(msg.0);
     }
 }
 
 impl Handler<GetWorkItems> for JobWorkerActor {
     type Result = Vec<JobItem>;
 
-    fn handle(&mut self, _msg: GetWorkItems, _ctx: &mut Self::Context) -> Self::Result {
-        let items = self
-            .work_items
-            .iter()
-            .filter(|work_item| work_item.status == JobStatus::Open)
-            .take(2)
-            .cloned()
-            .collect::<Vec<JobItem>>();
-
-        items
-            .iter()
-            .for_each(|work_item| self.set_in_progress(&work_item.id));
-
-        items
+    fn handle(&mut self, msg: GetWorkItems, _ctx: &mut Self::Context) -> Self::Result {
+        self.jobs.fetch(&msg.job_names, WORK_ITEMS_PER_REQUEST)
     }
 }
 
@@ -122,23 +390,92 @@ impl Handler<AddCompletionSubscriber> for JobWorkerActor {
     type Result = ();
 
     fn handle(&mut self, msg: AddCompletionSubscriber, _ctx: &mut Self::Context) -> Self::Result {
-        self.completed_subscribers.push(msg.0);
+        self.subscribers.insert(
+            msg.process_id,
+            JobSubscriber {
+                completed: msg.completed,
+                failed: msg.failed,
+            },
+        );
+    }
+}
+
+impl Handler<RemoveCompletionSubscriber> for JobWorkerActor {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: RemoveCompletionSubscriber,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        self.subscribers.remove(&msg.process_id);
+
+        let removed = self.jobs.remove_open_process_jobs(&msg.process_id);
+
+        if removed > 0 {
+            info!(
+                "Removed {} open jobs of ended process {}",
+                removed, msg.process_id
+            );
+        }
+    }
+}
+
+impl Handler<CompleteWorkItem> for JobWorkerActor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: CompleteWorkItem, _ctx: &mut Self::Context) -> Self::Result {
+        // Repeats and requests the job store rejects are not checked
+        if let Some(job_item) = self.jobs.get(&msg.job_id).filter(|job_item| {
+            job_item.status == JobStatus::InProgress
+                && msg
+                    .attempt
+                    .filter(|attempt| *attempt != job_item.attempts)
+                    .is_none()
+        }) {
+            self.check_outputs(job_item, &msg.outputs)?;
+        }
+
+        if !self.jobs.finish(
+            &msg.job_id,
+            msg.attempt,
+            JobStatus::Completed,
+            msg.idempotency_key,
+        )? {
+            return Ok(());
+        }
+
+        if let Some(subscriber) = self.owner_subscriber(&msg.job_id) {
+            subscriber
+                .completed
+                .do_send(JobCompletedMessage::new(msg.job_id, msg.outputs));
+        }
+
+        Ok(())
     }
 }
 
-impl Handler<JobCompletedMessage> for JobWorkerActor {
+impl Handler<FailWorkItem> for JobWorkerActor {
     type Result = Result<()>;
 
-    fn handle(&mut self, msg: JobCompletedMessage, _ctx: &mut Self::Context) -> Self::Result {
-        // TODO optimize this
-        self.work_items
-            .iter_mut()
-            .find(|work_item| work_item.id == msg.job_id)
-            .map(|work_item| work_item.status = JobStatus::Completed);
+    fn handle(&mut self, msg: FailWorkItem, _ctx: &mut Self::Context) -> Self::Result {
+        if !self.jobs.finish(
+            &msg.job_id,
+            msg.attempt,
+            JobStatus::Failed,
+            msg.idempotency_key,
+        )? {
+            return Ok(());
+        }
+
+        warn!("Job {} failed: {}", msg.job_id, msg.error);
 
-        self.completed_subscribers
-            .iter()
-            .for_each(|sub| sub.do_send(msg.clone()));
+        if let Some(subscriber) = self.owner_subscriber(&msg.job_id) {
+            subscriber.failed.do_send(JobFailedMessage {
+                job_id: msg.job_id,
+                error: msg.error,
+            });
+        }
 
         Ok(())
     }
@@ -1,5 +1,7 @@
 pub mod actor_step_context;
 pub mod engine_actor;
+pub mod job_store;
 pub mod job_worker_actor;
 pub mod process_actor;
 pub mod process_context;
+pub mod script_worker_actor;
@@ -1,35 +1,59 @@
 use std::{collections::HashMap, sync::Arc};
 
-use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, Message};
-use anyhow::{anyhow, Result};
+use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, Message, Recipient};
+use anyhow::Result;
 use log::{info, warn};
 use serde_json::{Map, Value};
 
 use super::{
     actor_step_context::ActorStepContext,
     engine_actor::EngineActor,
-    job_worker_actor::{JobCompletedMessage, JobWorkerActor},
+    job_worker_actor::{JobCompletedMessage, JobFailedMessage, JobWorkerActor},
+    script_worker_actor::{ScriptCompletedMessage, ScriptWorkerActor},
 };
 use crate::{
     actors::engine_actor,
     definition::{
         process_definition::ProcessDefinition,
-        step::{StepExecutionStatus, StepInputRequest, StepState},
+        schema_registry::SchemaRegistry,
+        step::{StepError, StepExecutionStatus, StepInputRequest, StepState},
     },
+    steps::{ploy_module::ScriptContext, script_engine::CONTRACT_VIOLATION},
 };
 
+/// Error type of steps mapping an output the step providing it does not have, e.g. because
+/// that step failed.
+pub const MISSING_INPUT: &str = "MissingInput";
+
 #[derive(Message)]
 #[rtype(result = "anyhow::Result<()>")]
 pub struct EndProcessMessage {}
 
+enum StepInputs {
+    Resolved(Map<String, Value>),
+    /// Some of the steps providing the inputs are still running
+    Waiting,
+    Missing(StepError),
+}
+
 pub struct ProcessActor {
     id: String,
+    // Set once the actor is started
+    address: Option<Addr<ProcessActor>>,
     process_engine: Addr<EngineActor>,
     job_worker: Addr<JobWorkerActor>,
+    script_worker: Addr<ScriptWorkerActor>,
+    schema_registry: Arc<SchemaRegistry>,
     process_definition: Arc<ProcessDefinition>,
     process_inputs: Map<String, Value>,
+    /// Inherited by its jobs and called processes
+    priority: i32,
+    /// Process started by a client, this process unless it was called by another one
+    root_process_id: String,
     jobs: HashMap<String, String>,
     steps: HashMap<String, StepState>,
+    // step_id -> steps waiting for its outputs
+    waiting_steps: HashMap<String, Vec<String>>,
 }
 
 impl ProcessActor {
@@ -37,89 +61,118 @@ impl ProcessActor {
         id: String,
         process_engine: Addr<EngineActor>,
         job_worker: Addr<JobWorkerActor>,
+        script_worker: Addr<ScriptWorkerActor>,
+        schema_registry: Arc<SchemaRegistry>,
         process_definition: Arc<ProcessDefinition>,
         process_inputs: Map<String, Value>,
     ) -> Self {
         let jobs = HashMap::default();
+        let root_process_id = id.clone();
 
         Self {
             id,
+            address: None,
             jobs,
             job_worker,
+            script_worker,
+            schema_registry,
             process_engine,
             process_inputs,
             process_definition,
+            priority: 0,
+            root_process_id,
             steps: HashMap::default(),
+            waiting_steps: HashMap::default(),
         }
     }
 
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_root_process_id(mut self, root_process_id: String) -> Self {
+        self.root_process_id = root_process_id;
+        self
+    }
+
+    /// The step is started again once the steps providing its inputs complete.
     fn resolve_input_requests(
         &mut self,
+        step_id: &str,
         input_requests: &Vec<StepInputRequest>,
-    ) -> Result<Map<String, Value>> {
+    ) -> Result<StepInputs> {
         let mut inputs = Map::default();
+        let mut waiting = false;
 
         for input_request in input_requests {
-            let from_step_state: Option<&StepState> = {
-                let state = self.steps.get(&input_request.from);
-
-                if state.is_some() {
-                    state
-                } else {
-                    self.start_step(input_request.from.clone())
-                        .expect("Start step failed");
+            if !self.steps.contains_key(&input_request.from) {
+                self.start_step(input_request.from.clone())?;
+            }
 
-                    self.steps.get(&input_request.from)
+            let from_step_state = self
+                .steps
+                .get(&input_request.from)
+                .expect("Step should have been executed first");
+
+            if !matches!(
+                from_step_state.status,
+                StepExecutionStatus::Completed | StepExecutionStatus::Failed
+            ) {
+                let waiting_steps = self
+                    .waiting_steps
+                    .entry(input_request.from.clone())
+                    .or_default();
+
+                if !waiting_steps
+                    .iter()
+                    .any(|waiting_step| waiting_step == step_id)
+                {
+                    waiting_steps.push(step_id.to_string());
                 }
+
+                waiting = true;
+                continue;
+            }
+
+            let Some(output) = from_step_state.outputs.get(input_request.output.as_str()) else {
+                return Ok(StepInputs::Missing(StepError::new(
+                    MISSING_INPUT,
+                    format!(
+                        "Step {} has no output '{}' for input '{}'",
+                        input_request.from, input_request.output, input_request.name
+                    ),
+                )));
             };
 
-            let outputs = from_step_state
-                .expect("Step should have been executed first")
-                .outputs
-                .clone();
-
-            inputs.insert(
-                input_request.name.clone(),
-                outputs
-                    .get(input_request.output.as_str())
-                    .expect(
-                        format!(
-                            "Step {} should have outputted {} value",
-                            input_request.from, input_request.output
-                        )
-                        .as_str(),
-                    )
-                    .clone(),
-            );
+            inputs.insert(input_request.name.clone(), output.clone());
         }
 
-        Ok(inputs)
-    }
-
-    fn validate_map(map: &Map<String, Value>, schema_name: &str) -> Result<()> {
-        let schema_contents =
-            std::fs::read_to_string(format!("data/schemas/{schema_name}.json")).unwrap();
+        if waiting {
+            return Ok(StepInputs::Waiting);
+        }
 
-        let schema: Value = serde_json::from_str(&schema_contents).unwrap();
+        Ok(StepInputs::Resolved(inputs))
+    }
 
+    fn validate_map(
+        process_definition: &ProcessDefinition,
+        schema_registry: &SchemaRegistry,
+        map: &Map<String, Value>,
+        schema_name: &str,
+    ) -> Result<()> {
         let value = Value::Object(map.clone());
 
-        let compiled = jsonschema::JSONSchema::compile(&schema).expect("A valid schema");
+        let result = match process_definition.get_compiled_schema(schema_name) {
+            Some(compiled) => SchemaRegistry::validate_compiled(compiled, &value),
+            None => schema_registry.validate(&value, schema_name),
+        };
 
-        let result = compiled.validate(&value);
-
-        if let Err(errors) = result {
-            let err_message = errors
-                .map(|err| err.to_string())
-                .collect::<Vec<String>>()
-                .join("\n");
-
-            warn!("Validation failed: {}", err_message);
-
-            Err(anyhow!(err_message))
-        } else {
-            Ok(())
+        if let Err(err) = &result {
+            warn!("Validation failed: {}", err);
         }
+
+        result
     }
 
     fn start_step(&mut self, step_id: String) -> Result<()> {
@@ -129,7 +182,20 @@ impl ProcessActor {
             .ok_or_else(|| anyhow::anyhow!("Step not found"))?
             .get_input_requests();
 
-        let inputs = self.resolve_input_requests(&input_requests)?;
+        let inputs = match self.resolve_input_requests(&step_id, &input_requests)? {
+            StepInputs::Resolved(inputs) => inputs,
+            StepInputs::Waiting => {
+                info!("Step {} waits for its inputs", step_id);
+                return Ok(());
+            }
+            StepInputs::Missing(error) => {
+                self.steps
+                    .entry(step_id.clone())
+                    .or_insert(StepState::new(step_id.clone()));
+
+                return self.fail_step(&step_id, error);
+            }
+        };
 
         let step = self
             .process_definition
@@ -137,9 +203,26 @@ impl ProcessActor {
             .ok_or_else(|| anyhow::anyhow!("Step not found"))?;
 
         if let Some(input_schema) = step.input_schema() {
-            Self::validate_map(&inputs, &input_schema)?;
+            if let Err(err) = Self::validate_map(
+                &self.process_definition,
+                &self.schema_registry,
+                &inputs,
+                &input_schema,
+            ) {
+                self.steps
+                    .entry(step_id.clone())
+                    .or_insert(StepState::new(step_id.clone()));
+
+                return self.fail_step(
+                    &step_id,
+                    StepError::new(CONTRACT_VIOLATION, err.to_string()),
+                );
+            }
         }
 
+        let script_results = self.script_results();
+        let script_context = self.script_context(&step_id);
+
         let step_state = self
             .steps
             .entry(step_id.clone())
@@ -153,8 +236,13 @@ impl ProcessActor {
             self.id.clone(),
             self.process_engine.clone(),
             self.job_worker.clone(),
+            self.script_worker.clone(),
+            script_results,
+            script_context,
             step_state.inputs.clone(),
-        );
+        )
+        .with_priority(self.priority)
+        .with_root_process_id(self.root_process_id.clone());
         let result = step.start(&ctx)?;
 
         match result {
@@ -172,6 +260,9 @@ impl ProcessActor {
                         outputs,
                     });
             }
+            crate::definition::step::StepResult::Failed(error) => {
+                self.fail_step(&step_id, error)?;
+            }
         }
 
         Ok(())
@@ -188,18 +279,111 @@ impl ProcessActor {
             .get_mut(step_id)
             .ok_or_else(|| anyhow::anyhow!("Step state not found"))?;
 
-        step_state.status = StepExecutionStatus::Completed;
-        step_state.outputs.extend(outputs);
+        let mut step_outputs = step_state.outputs.clone();
+        step_outputs.extend(outputs);
 
+        // Outputs violating the schema are not available to the next steps
         if let Some(output_schema) = step.output_schema() {
-            Self::validate_map(&step_state.outputs, &output_schema)?;
+            if let Err(err) = Self::validate_map(
+                &self.process_definition,
+                &self.schema_registry,
+                &step_outputs,
+                &output_schema,
+            ) {
+                return self
+                    .fail_step(step_id, StepError::new(CONTRACT_VIOLATION, err.to_string()));
+            }
         }
 
+        step_state.status = StepExecutionStatus::Completed;
+        step_state.outputs = step_outputs;
+
         self.execute_next_steps(step_id)?;
 
+        if let Some(waiting_steps) = self.waiting_steps.remove(step_id) {
+            for waiting_step_id in waiting_steps {
+                self.start_step(waiting_step_id)?;
+            }
+        }
+
         Ok(())
     }
 
+    /// Follows the error flows of the step, the process fails when there are none. The error is
+    /// available to the next steps as the `error` output of the failed step. Steps waiting for its
+    /// outputs are started again along the error flows and fail when they map any other.
+    fn fail_step(&mut self, step_id: &str, error: StepError) -> Result<()> {
+        let step_state = self
+            .steps
+            .get_mut(step_id)
+            .ok_or_else(|| anyhow::anyhow!("Step state not found"))?;
+
+        step_state.status = StepExecutionStatus::Failed;
+        step_state
+            .outputs
+            .insert("error".to_string(), error.to_value());
+        step_state.error = Some(error.clone());
+
+        let waiting_steps = self.waiting_steps.remove(step_id).unwrap_or_default();
+
+        let error_steps = self
+            .process_definition
+            .get_next(step_id)
+            .map(|next_steps| {
+                next_steps
+                    .iter()
+                    .filter(|next_step| next_step.on_error)
+                    .map(|next_step| next_step.to.clone())
+                    .collect::<Vec<String>>()
+            })
+            .unwrap_or_default();
+
+        if error_steps.is_empty() {
+            warn!("Step {} failed: {}", step_id, error);
+
+            self.process_engine
+                .do_send(engine_actor::FailProcessMessage {
+                    process_id: self.id.clone(),
+                    step_id: step_id.to_string(),
+                    error,
+                });
+
+            return Ok(());
+        }
+
+        info!(
+            "Step {} failed, following its error flow: {}",
+            step_id, error
+        );
+
+        for error_step_id in error_steps {
+            self.start_step(error_step_id)?;
+        }
+
+        for waiting_step_id in waiting_steps {
+            self.start_step(waiting_step_id)?;
+        }
+
+        Ok(())
+    }
+
+    fn script_results(&self) -> Recipient<ScriptCompletedMessage> {
+        self.address
+            .clone()
+            .expect("Process actor should be started")
+            .recipient()
+    }
+
+    fn script_context(&self, step_id: &str) -> ScriptContext {
+        ScriptContext {
+            process_id: self.id.clone(),
+            step_id: step_id.to_string(),
+            definition_name: self.process_definition.get_name().to_string(),
+            definition_version: self.process_definition.get_version().to_string(),
+            variables: self.process_inputs.clone(),
+        }
+    }
+
     fn get_actor_step_context(&self, step_id: &str) -> Result<ActorStepContext> {
         let step_state = self
             .steps
@@ -210,8 +394,13 @@ impl ProcessActor {
             self.id.clone(),
             self.process_engine.clone(),
             self.job_worker.clone(),
+            self.script_worker.clone(),
+            self.script_results(),
+            self.script_context(step_id),
             step_state.inputs.clone(),
-        ))
+        )
+        .with_priority(self.priority)
+        .with_root_process_id(self.root_process_id.clone()))
     }
 
     fn execute_next_steps(&mut self, step_id: &str) -> Result<()> {
@@ -242,6 +431,8 @@ impl Actor for ProcessActor {
     type Context = actix::Context<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
+        self.address = Some(ctx.address());
+
         let mut start_step_state = StepState::new(self.process_definition.get_start_step_id());
         start_step_state.inputs.extend(self.process_inputs.clone());
 
@@ -251,15 +442,25 @@ impl Actor for ProcessActor {
         );
 
         self.job_worker
-            .do_send(crate::actors::job_worker_actor::AddCompletionSubscriber(
-                ctx.address().recipient(),
-            ));
+            .do_send(crate::actors::job_worker_actor::AddCompletionSubscriber {
+                process_id: self.id.clone(),
+                completed: ctx.address().recipient(),
+                failed: ctx.address().recipient(),
+            });
 
         if let Err(err) = self.start_step(self.process_definition.get_start_step_id()) {
             ctx.stop();
             log::error!("Failed to start process: {}", err);
         }
     }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.job_worker.do_send(
+            crate::actors::job_worker_actor::RemoveCompletionSubscriber {
+                process_id: self.id.clone(),
+            },
+        );
+    }
 }
 
 impl Handler<JobCompletedMessage> for ProcessActor {
@@ -277,6 +478,35 @@ impl Handler<JobCompletedMessage> for ProcessActor {
     }
 }
 
+impl Handler<ScriptCompletedMessage> for ProcessActor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: ScriptCompletedMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let step_id = self
+            .jobs
+            .remove(&msg.job_id)
+            .ok_or_else(|| anyhow::anyhow!("Script {} not found", msg.job_id))?;
+
+        match msg.result {
+            Ok(outputs) => self.complete_step(&step_id, outputs),
+            Err(error) => self.fail_step(&step_id, error),
+        }
+    }
+}
+
+impl Handler<JobFailedMessage> for ProcessActor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: JobFailedMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let step_id = self
+            .jobs
+            .remove(&msg.job_id)
+            .ok_or_else(|| anyhow::anyhow!("Job {} not found", msg.job_id))?;
+
+        self.fail_step(&step_id, msg.error)
+    }
+}
+
 impl Handler<EndProcessMessage> for ProcessActor {
     type Result = Result<()>;
 
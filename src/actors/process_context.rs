@@ -2,6 +2,8 @@ use core::fmt;
 
 use actix::Addr;
 
+use crate::definition::step::StepError;
+
 use super::process_actor::ProcessActor;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -24,18 +26,34 @@ impl fmt::Display for ProcessState {
 #[derive(Clone, Debug)]
 pub struct ProcessContext {
     pub process_id: String,
+    /// Process started by a client, this process unless it was called by another one
+    pub root_process_id: String,
     pub process_addr: Option<Addr<ProcessActor>>,
     pub state: ProcessState,
     pub outputs: Option<serde_json::Map<String, serde_json::Value>>,
+    pub error: Option<ProcessError>,
+}
+
+/// Failure of a step that had no error flow to handle it.
+#[derive(Clone, Debug)]
+pub struct ProcessError {
+    pub step_id: String,
+    pub error: StepError,
 }
 
 impl ProcessContext {
-    pub fn new(process_id: String, process_addr: Addr<ProcessActor>) -> Self {
+    pub fn new(
+        process_id: String,
+        root_process_id: String,
+        process_addr: Addr<ProcessActor>,
+    ) -> Self {
         Self {
             process_id,
+            root_process_id,
             process_addr: Some(process_addr),
             state: ProcessState::Running,
             outputs: None,
+            error: None,
         }
     }
 }
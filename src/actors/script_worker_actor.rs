@@ -0,0 +1,107 @@
+use std::{sync::Arc, time::Duration};
+
+use actix::{Actor, Addr, Handler, Message, Recipient, SyncArbiter, SyncContext};
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+use crate::{
+    definition::step::StepError,
+    steps::{
+        interpreter_pool,
+        ploy_module::ScriptContext,
+        sandbox::{SandboxOverrides, SandboxPolicy},
+        script::ScriptSource,
+        script_cache::ScriptCache,
+        script_engine::ScriptEngines,
+    },
+};
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ExecuteScriptMessage {
+    pub job_id: String,
+    pub script: ScriptSource,
+    pub inputs: Map<String, Value>,
+    pub timeout: Duration,
+    pub sandbox: SandboxOverrides,
+    /// Outputs are looked up in and stored to the script cache
+    pub pure: bool,
+    pub context: ScriptContext,
+    pub reply_to: Recipient<ScriptCompletedMessage>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<()>")]
+pub struct ScriptCompletedMessage {
+    pub job_id: String,
+    pub result: Result<Map<String, Value>, StepError>,
+}
+
+/// Runs scripts on a dedicated thread, so they never block the arbiter of the process actors.
+/// Every worker thread keeps its own warmed interpreter and engine of each runtime.
+pub struct ScriptWorkerActor {
+    sandbox_policy: Arc<SandboxPolicy>,
+    script_cache: Arc<ScriptCache>,
+    engines: ScriptEngines,
+}
+
+impl ScriptWorkerActor {
+    pub fn start_workers(
+        threads: usize,
+        sandbox_policy: Arc<SandboxPolicy>,
+        script_cache: Arc<ScriptCache>,
+    ) -> Addr<Self> {
+        SyncArbiter::start(threads, move || ScriptWorkerActor {
+            sandbox_policy: sandbox_policy.clone(),
+            script_cache: script_cache.clone(),
+            engines: ScriptEngines::default(),
+        })
+    }
+}
+
+impl Actor for ScriptWorkerActor {
+    type Context = SyncContext<Self>;
+
+    fn started(&mut self, _ctx: &mut Self::Context) {
+        interpreter_pool::warm_up();
+    }
+}
+
+impl Handler<ExecuteScriptMessage> for ScriptWorkerActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ExecuteScriptMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let cache_key = msg
+            .pure
+            .then(|| ScriptCache::key(&msg.script, &msg.inputs))
+            .flatten();
+
+        let cached = cache_key
+            .as_ref()
+            .and_then(|(key, inputs)| self.script_cache.get(key, inputs));
+
+        let result = match cached {
+            Some(outputs) => Ok(outputs),
+            None => {
+                let result = self.engines.get(msg.script.runtime()).execute(
+                    &msg.script,
+                    Value::Object(msg.inputs),
+                    Some(msg.timeout),
+                    &self.sandbox_policy.with_overrides(&msg.sandbox),
+                    &msg.context,
+                );
+
+                if let (Some((key, inputs)), Ok(outputs)) = (cache_key, &result) {
+                    self.script_cache.insert(key, inputs, outputs.clone());
+                }
+
+                result
+            }
+        };
+
+        msg.reply_to.do_send(ScriptCompletedMessage {
+            job_id: msg.job_id,
+            result,
+        });
+    }
+}
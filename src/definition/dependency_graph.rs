@@ -0,0 +1,317 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use semver::{Version, VersionReq};
+
+use super::{process_definition::ProcessDefinition, validator::ValidationError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessDependency {
+    pub process: String,
+    pub step_id: String,
+    pub called_process: String,
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallCycle {
+    pub step_id: String,
+    pub processes: Vec<String>,
+}
+
+/// Call dependencies between process definitions, as declared by their call steps.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    versions: BTreeMap<String, Version>,
+    dependencies: BTreeMap<String, Vec<ProcessDependency>>,
+    // Found whenever the graph changes
+    cycles: Vec<CallCycle>,
+}
+
+impl DependencyGraph {
+    pub fn build<'a>(definitions: impl Iterator<Item = &'a ProcessDefinition>) -> Self {
+        let mut graph = Self::default();
+
+        for definition in definitions {
+            graph.add(definition);
+        }
+
+        graph.cycles = graph.find_cycles();
+        graph
+    }
+
+    /// Adds the process definition, replacing the one of the same name.
+    pub fn insert(&mut self, definition: &ProcessDefinition) {
+        self.add(definition);
+        self.cycles = self.find_cycles();
+    }
+
+    pub fn remove(&mut self, process_name: &str) {
+        self.versions.remove(process_name);
+        self.dependencies.remove(process_name);
+        self.cycles = self.find_cycles();
+    }
+
+    fn add(&mut self, definition: &ProcessDefinition) {
+        let mut dependencies = definition
+            .get_steps()
+            .filter_map(|step| {
+                step.called_process().map(|process| ProcessDependency {
+                    process: definition.get_name().to_string(),
+                    step_id: step.id(),
+                    called_process: process.name,
+                    version: process.version,
+                })
+            })
+            .collect::<Vec<ProcessDependency>>();
+
+        dependencies.sort_by(|a, b| a.step_id.cmp(&b.step_id));
+
+        self.versions.insert(
+            definition.get_name().to_string(),
+            definition.get_version().clone(),
+        );
+        self.dependencies
+            .insert(definition.get_name().to_string(), dependencies);
+    }
+
+    pub fn get_processes(&self) -> Vec<String> {
+        self.versions.keys().cloned().collect()
+    }
+
+    pub fn get_dependencies(&self) -> Vec<ProcessDependency> {
+        self.dependencies.values().flatten().cloned().collect()
+    }
+
+    fn get_process_dependencies(&self, process_name: &str) -> &[ProcessDependency] {
+        self.dependencies
+            .get(process_name)
+            .map(|dependencies| dependencies.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Processes calling the given process, directly or through other sub-processes.
+    pub fn get_dependents(&self, process_name: &str) -> Vec<String> {
+        let mut dependents: Vec<String> = Vec::default();
+        let mut queue = VecDeque::from([process_name.to_string()]);
+
+        while let Some(called_process) = queue.pop_front() {
+            for dependency in self.dependencies.values().flatten() {
+                if dependency.called_process == called_process
+                    && dependency.process != process_name
+                    && !dependents.contains(&dependency.process)
+                {
+                    dependents.push(dependency.process.clone());
+                    queue.push_back(dependency.process.clone());
+                }
+            }
+        }
+
+        dependents.sort();
+        dependents
+    }
+
+    /// Length of the longest chain of nested calls starting from the given process. Recursive
+    /// calls are not followed.
+    pub fn get_call_depth(&self, process_name: &str) -> usize {
+        self.find_call_depth(process_name, &mut Vec::default())
+    }
+
+    fn find_call_depth(&self, process_name: &str, stack: &mut Vec<String>) -> usize {
+        stack.push(process_name.to_string());
+
+        let mut depth = 0;
+
+        for dependency in self.get_process_dependencies(process_name) {
+            if !stack.contains(&dependency.called_process) {
+                depth = depth.max(1 + self.find_call_depth(&dependency.called_process, stack));
+            }
+        }
+
+        stack.pop();
+
+        depth
+    }
+
+    /// Processes called by the given process, directly or through other sub-processes. The
+    /// process itself is only included if it is called recursively.
+    fn get_called_processes(&self, process_name: &str) -> BTreeSet<String> {
+        let mut called_processes = BTreeSet::default();
+        let mut queue = VecDeque::from([process_name.to_string()]);
+
+        while let Some(process) = queue.pop_front() {
+            for dependency in self.get_process_dependencies(&process) {
+                if called_processes.insert(dependency.called_process.clone()) {
+                    queue.push_back(dependency.called_process.clone());
+                }
+            }
+        }
+
+        called_processes
+    }
+
+    /// Shortest chain of calls leading back to the calling process through the given call.
+    fn find_cycle(&self, call: &ProcessDependency) -> Option<CallCycle> {
+        let mut callers = HashMap::from([(call.called_process.as_str(), call)]);
+        let mut queue = VecDeque::from([call]);
+
+        while let Some(dependency) = queue.pop_front() {
+            if dependency.called_process == call.process {
+                let mut processes = vec![dependency.called_process.clone()];
+                let mut caller = dependency;
+
+                loop {
+                    processes.push(caller.process.clone());
+
+                    if std::ptr::eq(caller, call) {
+                        break;
+                    }
+
+                    caller = callers[caller.process.as_str()];
+                }
+
+                processes.reverse();
+
+                return Some(CallCycle {
+                    step_id: dependency.step_id.clone(),
+                    processes,
+                });
+            }
+
+            for next in self.get_process_dependencies(&dependency.called_process) {
+                if !callers.contains_key(next.called_process.as_str()) {
+                    callers.insert(&next.called_process, next);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// A call cycle for every group of processes calling each other, i.e. every strongly
+    /// connected component of the graph with a recursive call. Cycles start from the first
+    /// process of their group by name.
+    pub fn get_cycles(&self) -> &[CallCycle] {
+        &self.cycles
+    }
+
+    fn find_cycles(&self) -> Vec<CallCycle> {
+        let called_processes = self
+            .versions
+            .keys()
+            .map(|process| (process.as_str(), self.get_called_processes(process)))
+            .collect::<HashMap<&str, BTreeSet<String>>>();
+
+        let mut grouped = HashSet::<&str>::default();
+        let mut cycles = Vec::default();
+
+        for process in self.versions.keys() {
+            if grouped.contains(process.as_str())
+                || !called_processes[process.as_str()].contains(process)
+            {
+                continue;
+            }
+
+            grouped.extend(
+                called_processes[process.as_str()]
+                    .iter()
+                    .filter(|called| {
+                        called_processes
+                            .get(called.as_str())
+                            .is_some_and(|calls| calls.contains(process))
+                    })
+                    .map(|called| called.as_str()),
+            );
+
+            cycles.extend(
+                self.get_process_dependencies(process)
+                    .iter()
+                    .find_map(|dependency| self.find_cycle(dependency)),
+            );
+        }
+
+        cycles
+    }
+
+    /// Checks that processes called by the given process exist in a compatible version and
+    /// that no recursive calls can be reached from it. Errors are reported on the steps of the
+    /// given process leading to them.
+    pub fn validate(&self, process_name: &str) -> Vec<ValidationError> {
+        let mut errors = Vec::default();
+        let dependencies = self.get_process_dependencies(process_name);
+
+        for dependency in dependencies {
+            if let Err(message) = self.check_dependency(dependency) {
+                errors.push(ValidationError::new(dependency.step_id.clone(), message));
+            }
+
+            if let Some(cycle) = self.find_cycle(dependency) {
+                errors.push(ValidationError::new(
+                    dependency.step_id.clone(),
+                    format!("Recursive call detected: {}", cycle.processes.join(" -> ")),
+                ));
+            }
+        }
+
+        // Cycles of other processes, the ones going through the process were reported above
+        for cycle in &self.cycles {
+            if cycle
+                .processes
+                .iter()
+                .any(|process| process == process_name)
+            {
+                continue;
+            }
+
+            for dependency in dependencies {
+                let mut called_processes = self.get_called_processes(&dependency.called_process);
+                called_processes.insert(dependency.called_process.clone());
+
+                if called_processes.contains(&cycle.processes[0]) {
+                    errors.push(ValidationError::new(
+                        dependency.step_id.clone(),
+                        format!(
+                            "Step {} leads to a recursive call: {}",
+                            dependency.step_id,
+                            cycle.processes.join(" -> ")
+                        ),
+                    ));
+                }
+            }
+        }
+
+        errors
+    }
+
+    fn check_dependency(&self, dependency: &ProcessDependency) -> Result<(), String> {
+        let version = self
+            .versions
+            .get(&dependency.called_process)
+            .ok_or_else(|| {
+                format!(
+                    "Step {} calls unknown process {}",
+                    dependency.step_id, dependency.called_process
+                )
+            })?;
+
+        let Some(requirement) = &dependency.version else {
+            return Ok(());
+        };
+
+        let requirement = VersionReq::parse(requirement).map_err(|e| {
+            format!(
+                "Step {} has invalid version requirement '{}': {}",
+                dependency.step_id, requirement, e
+            )
+        })?;
+
+        if !requirement.matches(version) {
+            return Err(format!(
+                "Step {} requires process {} {}, but version {} is deployed",
+                dependency.step_id, dependency.called_process, requirement, version
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use log::info;
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+const JOB_SPECIFICATION_TYPE: &str = "JobSpecification";
+
+#[derive(Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum JobParameterType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Object,
+    Array,
+}
+
+impl JobParameterType {
+    /// JSON Schema `type` keyword value matching the parameter type.
+    pub fn schema_type(&self) -> &'static str {
+        match self {
+            JobParameterType::String => "string",
+            JobParameterType::Number => "number",
+            JobParameterType::Integer => "integer",
+            JobParameterType::Boolean => "boolean",
+            JobParameterType::Object => "object",
+            JobParameterType::Array => "array",
+        }
+    }
+
+    pub fn matches(&self, value: &Value) -> bool {
+        match self {
+            JobParameterType::String => value.is_string(),
+            JobParameterType::Number => value.is_number(),
+            JobParameterType::Integer => value.is_i64() || value.is_u64(),
+            JobParameterType::Boolean => value.is_boolean(),
+            JobParameterType::Object => value.is_object(),
+            JobParameterType::Array => value.is_array(),
+        }
+    }
+}
+
+impl fmt::Display for JobParameterType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+pub struct JobParameter {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub rtype: JobParameterType,
+    #[serde(default)]
+    pub optional: bool,
+}
+
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+pub struct JobSpecification {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub version: Version,
+    pub name: String,
+    #[serde(default)]
+    pub inputs: Vec<JobParameter>,
+    #[serde(default)]
+    pub outputs: Vec<JobParameter>,
+}
+
+impl JobSpecification {
+    pub fn get_input(&self, name: &str) -> Option<&JobParameter> {
+        self.inputs.iter().find(|input| input.name == name)
+    }
+
+    /// Checks job outputs against the output contract, returning all found violations.
+    pub fn validate_outputs(&self, outputs: &Map<String, Value>) -> Result<(), Vec<String>> {
+        let mut errors = Vec::default();
+
+        for output in self.outputs.iter() {
+            match outputs.get(&output.name) {
+                Some(value) if !output.rtype.matches(value) => errors.push(format!(
+                    "Output '{}' should be of type {}",
+                    output.name, output.rtype
+                )),
+                None if !output.optional => {
+                    errors.push(format!("Required output '{}' is missing", output.name))
+                }
+                _ => {}
+            }
+        }
+
+        for name in outputs.keys() {
+            if !self.outputs.iter().any(|output| &output.name == name) {
+                errors.push(format!(
+                    "Output '{}' is not declared by job {} {}",
+                    name, self.name, self.version
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Job specifications known to the engine, grouped by job name and ordered by version.
+#[derive(Debug, Default)]
+pub struct JobCatalog {
+    jobs: HashMap<String, BTreeMap<Version, Arc<JobSpecification>>>,
+}
+
+impl JobCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every `.yaml`/`.yml` job specification from the directory.
+    pub fn load(directory: &str) -> Result<Self> {
+        let mut catalog = Self::new();
+
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+
+            let is_yaml = path
+                .extension()
+                .is_some_and(|extension| extension == "yaml" || extension == "yml");
+
+            if is_yaml {
+                catalog.add(Self::load_specification(&path)?)?;
+            }
+        }
+
+        Ok(catalog)
+    }
+
+    fn load_specification(path: &Path) -> Result<JobSpecification> {
+        let file_contents = std::fs::read_to_string(path)?;
+
+        let specification: JobSpecification = serde_yaml::from_str(&file_contents)
+            .map_err(|e| anyhow!("Invalid job specification {}: {}", path.display(), e))?;
+
+        if specification.kind != JOB_SPECIFICATION_TYPE {
+            return Err(anyhow!(
+                "Invalid job specification {}: expected type {}, found {}",
+                path.display(),
+                JOB_SPECIFICATION_TYPE,
+                specification.kind
+            ));
+        }
+
+        Ok(specification)
+    }
+
+    pub fn add(&mut self, specification: JobSpecification) -> Result<()> {
+        let versions = self.jobs.entry(specification.name.clone()).or_default();
+
+        if versions.contains_key(&specification.version) {
+            return Err(anyhow!(
+                "Job {} {} is specified more than once",
+                specification.name,
+                specification.version
+            ));
+        }
+
+        info!(
+            "Job specification loaded: {} {}",
+            specification.name, specification.version
+        );
+
+        versions.insert(specification.version.clone(), Arc::new(specification));
+
+        Ok(())
+    }
+
+    /// Finds the latest job specification matching the version requirement, or the latest
+    /// specification overall when no requirement is given.
+    pub fn get(&self, name: &str, version: Option<&str>) -> Result<Arc<JobSpecification>> {
+        let versions = self
+            .jobs
+            .get(name)
+            .ok_or_else(|| anyhow!("Job {} is not specified", name))?;
+
+        let requirement = match version {
+            Some(version) => VersionReq::parse(version)
+                .map_err(|e| anyhow!("Invalid version requirement '{}': {}", version, e))?,
+            None => VersionReq::STAR,
+        };
+
+        versions
+            .iter()
+            .rev()
+            .find(|(version, _)| requirement.matches(version))
+            .map(|(_, specification)| specification.clone())
+            .ok_or_else(|| anyhow!("Job {} has no version matching {}", name, requirement))
+    }
+
+    pub fn list(&self) -> Vec<Arc<JobSpecification>> {
+        let mut specifications = self
+            .jobs
+            .values()
+            .flat_map(|versions| versions.values().cloned())
+            .collect::<Vec<Arc<JobSpecification>>>();
+
+        specifications.sort_by(|a, b| a.name.cmp(&b.name).then(a.version.cmp(&b.version)));
+
+        specifications
+    }
+}
@@ -0,0 +1,263 @@
+use std::{collections::HashSet, fmt, str::FromStr};
+
+use anyhow::anyhow;
+
+use super::{
+    dependency_graph::DependencyGraph,
+    process_definition::ProcessDefinition,
+    schema_registry::SchemaRegistry,
+    step::{Step, StepType},
+};
+
+const DEFAULT_MAX_CALL_DEPTH: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LintRule {
+    ActivityWithoutName,
+    UnusedDataNode,
+    UnmappedScriptOutput,
+    DeepCallChain,
+    ConditionWithoutDefaultFlow,
+    OrphanDiagramPosition,
+}
+
+impl LintRule {
+    pub const ALL: [LintRule; 6] = [
+        LintRule::ActivityWithoutName,
+        LintRule::UnusedDataNode,
+        LintRule::UnmappedScriptOutput,
+        LintRule::DeepCallChain,
+        LintRule::ConditionWithoutDefaultFlow,
+        LintRule::OrphanDiagramPosition,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            LintRule::ActivityWithoutName => "activity-without-name",
+            LintRule::UnusedDataNode => "unused-data-node",
+            LintRule::UnmappedScriptOutput => "unmapped-script-output",
+            LintRule::DeepCallChain => "deep-call-chain",
+            LintRule::ConditionWithoutDefaultFlow => "condition-without-default-flow",
+            LintRule::OrphanDiagramPosition => "orphan-diagram-position",
+        }
+    }
+}
+
+impl fmt::Display for LintRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl FromStr for LintRule {
+    type Err = anyhow::Error;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|rule| rule.code() == code)
+            .ok_or_else(|| anyhow!("Unknown lint rule: {}", code))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+    pub disabled_rules: HashSet<LintRule>,
+    pub max_call_depth: usize,
+}
+
+impl LintConfig {
+    pub fn is_enabled(&self, rule: LintRule) -> bool {
+        !self.disabled_rules.contains(&rule)
+    }
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            disabled_rules: HashSet::default(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintWarning {
+    pub rule: LintRule,
+    pub step_id: String,
+    pub message: String,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] [{}] {}", self.rule, self.step_id, self.message)
+    }
+}
+
+/// Reports smells in valid process definitions, which do not prevent them from being executed.
+pub struct ProcessLinter<'a> {
+    process_definition: &'a ProcessDefinition,
+    dependency_graph: &'a DependencyGraph,
+    schema_registry: &'a SchemaRegistry,
+    config: &'a LintConfig,
+    warnings: Vec<LintWarning>,
+}
+
+impl<'a> ProcessLinter<'a> {
+    pub fn lint(
+        process_definition: &'a ProcessDefinition,
+        dependency_graph: &'a DependencyGraph,
+        schema_registry: &'a SchemaRegistry,
+        config: &'a LintConfig,
+    ) -> Vec<LintWarning> {
+        let mut linter = ProcessLinter {
+            process_definition,
+            dependency_graph,
+            schema_registry,
+            config,
+            warnings: Vec::default(),
+        };
+
+        let mut steps = process_definition.get_steps().collect::<Vec<&dyn Step>>();
+        steps.sort_by_key(|step| step.id());
+
+        for step in steps {
+            linter.lint_step(step);
+        }
+
+        linter.lint_call_depth();
+        linter.lint_diagram();
+
+        linter.warnings
+    }
+
+    fn report(&mut self, rule: LintRule, step_id: &str, message: String) {
+        if self.config.is_enabled(rule) {
+            self.warnings.push(LintWarning {
+                rule,
+                step_id: step_id.to_string(),
+                message,
+            });
+        }
+    }
+
+    fn lint_step(&mut self, step: &dyn Step) {
+        let step_id = step.id();
+
+        match step.get_type() {
+            StepType::ActivityStep => {
+                let name = step.name().filter(|name| !name.trim().is_empty());
+
+                if name.is_none() {
+                    self.report(
+                        LintRule::ActivityWithoutName,
+                        &step_id,
+                        format!("Activity {} has no name", step_id),
+                    );
+                }
+            }
+            StepType::DataStep if !self.is_consumed(&step_id, None) => {
+                self.report(
+                    LintRule::UnusedDataNode,
+                    &step_id,
+                    format!("Data node {} is never consumed", step_id),
+                );
+            }
+            StepType::ScriptStep => self.lint_script_outputs(step),
+            StepType::ConditionStep => {
+                let has_default_flow =
+                    self.process_definition
+                        .get_next(&step_id)
+                        .is_some_and(|next_steps| {
+                            next_steps
+                                .iter()
+                                .any(|next| next.input.is_none() && !next.on_error)
+                        });
+
+                if !has_default_flow {
+                    self.report(
+                        LintRule::ConditionWithoutDefaultFlow,
+                        &step_id,
+                        format!("Condition {} has no default flow", step_id),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn lint_script_outputs(&mut self, step: &dyn Step) {
+        let step_id = step.id();
+
+        let Some(output_schema) = step.output_schema() else {
+            return;
+        };
+
+        // Missing schemas are reported by the validator
+        let Ok(output_schema) = self.schema_registry.get(&output_schema) else {
+            return;
+        };
+
+        let Some(properties) = output_schema.schema["properties"].as_object() else {
+            return;
+        };
+
+        for output in properties.keys() {
+            if !self.is_consumed(&step_id, Some(output)) {
+                self.report(
+                    LintRule::UnmappedScriptOutput,
+                    &step_id,
+                    format!("Output '{}' of script {} is never mapped", output, step_id),
+                );
+            }
+        }
+    }
+
+    fn is_consumed(&self, step_id: &str, output: Option<&str>) -> bool {
+        self.process_definition.get_steps().any(|step| {
+            step.get_input_requests().iter().any(|input_request| {
+                input_request.from == step_id
+                    && match output {
+                        Some(output) => input_request.output == output,
+                        None => true,
+                    }
+            })
+        })
+    }
+
+    fn lint_call_depth(&mut self) {
+        let process_name = self.process_definition.get_name();
+        let depth = self.dependency_graph.get_call_depth(process_name);
+
+        if depth > self.config.max_call_depth {
+            let start_step_id = self.process_definition.get_start_step_id();
+
+            self.report(
+                LintRule::DeepCallChain,
+                &start_step_id,
+                format!(
+                    "Process {} nests {} levels of calls, more than {}",
+                    process_name, depth, self.config.max_call_depth
+                ),
+            );
+        }
+    }
+
+    fn lint_diagram(&mut self) {
+        let orphan_positions = self
+            .process_definition
+            .get_diagram()
+            .iter()
+            .filter(|position| self.process_definition.get_step(&position.id).is_none())
+            .map(|position| position.id.clone())
+            .collect::<Vec<String>>();
+
+        for position_id in orphan_positions {
+            self.report(
+                LintRule::OrphanDiagramPosition,
+                &position_id,
+                format!("Diagram position refers to missing node {}", position_id),
+            );
+        }
+    }
+}
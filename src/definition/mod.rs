@@ -1,5 +1,10 @@
+pub mod dependency_graph;
+pub mod job_catalog;
+pub mod linter;
+mod nodes;
 pub mod parser;
 pub mod process_definition;
+pub mod proto_contracts;
+pub mod schema_registry;
 pub mod step;
 pub mod validator;
-mod nodes;
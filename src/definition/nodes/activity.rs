@@ -1,6 +1,11 @@
+use std::collections::BTreeMap;
+
 use serde::Deserialize;
 
-use crate::steps::activity::ActivityStep;
+use crate::{
+    definition::step::JobReference,
+    steps::activity::{ActivityPriority, ActivityStep},
+};
 
 use super::input_requests::InputRequests;
 
@@ -9,15 +14,40 @@ pub struct ActivityNode {
     #[serde(rename = "@id")]
     pub id: String,
     #[serde(rename = "@name")]
-    pub name: String,
+    pub name: Option<String>,
     #[serde(rename = "@input")]
     pub input: String,
     #[serde(rename = "@output")]
     pub output: String,
     #[serde(rename = "@job")]
     pub job: String,
+    #[serde(rename = "@jobVersion")]
+    pub job_version: Option<String>,
     #[serde(rename = "Inputs")]
     pub inputs: InputRequests,
+    /// Priority of the job, the process priority when not set
+    #[serde(rename = "@priority")]
+    pub priority: Option<i32>,
+    /// Input holding the priority of the job, `@priority` is used when it is not a number
+    #[serde(rename = "@priorityInput")]
+    pub priority_input: Option<String>,
+    #[serde(rename = "Headers")]
+    pub headers: Option<HeadersNode>,
+}
+
+/// Custom headers handed to the workers with the job.
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+pub struct HeadersNode {
+    #[serde(rename = "$value", default)]
+    pub headers: Vec<HeaderNode>,
+}
+
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+pub struct HeaderNode {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@value")]
+    pub value: String,
 }
 
 impl Into<ActivityStep> for ActivityNode {
@@ -27,8 +57,23 @@ impl Into<ActivityStep> for ActivityNode {
             self.name,
             self.input,
             self.output,
-            self.job,
+            JobReference::new(self.job, self.job_version),
             self.inputs.into(),
         )
+        .with_priority(ActivityPriority {
+            value: self.priority,
+            input: self.priority_input,
+        })
+        .with_headers(
+            self.headers
+                .map(|headers| {
+                    headers
+                        .headers
+                        .into_iter()
+                        .map(|header| (header.name, header.value))
+                        .collect::<BTreeMap<String, String>>()
+                })
+                .unwrap_or_default(),
+        )
     }
 }
@@ -1,6 +1,6 @@
 use serde::Deserialize;
 
-use crate::steps::call::CallStep;
+use crate::{definition::step::ProcessReference, steps::call::CallStep};
 
 use super::input_requests::InputRequests;
 
@@ -10,12 +10,18 @@ pub struct CallNode {
     pub id: String,
     #[serde(rename = "@process")]
     pub process: String,
+    #[serde(rename = "@version")]
+    pub version: Option<String>,
     #[serde(rename = "Inputs")]
     pub inputs: InputRequests,
 }
 
 impl Into<CallStep> for CallNode {
     fn into(self) -> CallStep {
-        CallStep::new(self.id, self.process, self.inputs.into())
+        CallStep::new(
+            self.id,
+            ProcessReference::new(self.process, self.version),
+            self.inputs.into(),
+        )
     }
 }
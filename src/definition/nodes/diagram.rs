@@ -0,0 +1,33 @@
+use serde::Deserialize;
+
+use crate::definition::process_definition::DiagramPosition;
+
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+pub struct PositionNode {
+    #[serde(rename = "@id")]
+    id: String,
+    #[serde(rename = "@x")]
+    x: f64,
+    #[serde(rename = "@y")]
+    y: f64,
+}
+
+#[derive(Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct DiagramNodes {
+    #[serde(rename = "Position", default)]
+    positions: Vec<PositionNode>,
+}
+
+impl From<DiagramNodes> for Vec<DiagramPosition> {
+    fn from(diagram: DiagramNodes) -> Self {
+        diagram
+            .positions
+            .into_iter()
+            .map(|position| DiagramPosition {
+                id: position.id,
+                x: position.x,
+                y: position.y,
+            })
+            .collect()
+    }
+}
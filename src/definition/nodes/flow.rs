@@ -4,6 +4,12 @@ use serde::Deserialize;
 
 use crate::definition::step::FlowLeaf;
 
+#[derive(Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowEvent {
+    Error,
+}
+
 #[derive(Deserialize, PartialEq, Debug, Clone)]
 pub struct FlowNode {
     #[serde(rename = "@from")]
@@ -12,6 +18,8 @@ pub struct FlowNode {
     to: String,
     #[serde(rename = "@input")]
     input: Option<String>,
+    #[serde(rename = "@on")]
+    on: Option<FlowEvent>,
 }
 
 #[derive(Deserialize, PartialEq, Debug, Clone)]
@@ -28,11 +36,23 @@ impl Into<HashMap<String, Vec<FlowLeaf>>> for FlowNodes {
             let from = n.from.clone();
             let to = n.to.clone();
             let input = n.input.clone();
+            let on_error = n.on == Some(FlowEvent::Error);
 
             if let Some(f) = flow.get_mut(&from) {
-                f.push(FlowLeaf { to, input });
+                f.push(FlowLeaf {
+                    to,
+                    input,
+                    on_error,
+                });
             } else {
-                flow.insert(from, vec![FlowLeaf { to, input }]);
+                flow.insert(
+                    from,
+                    vec![FlowLeaf {
+                        to,
+                        input,
+                        on_error,
+                    }],
+                );
             }
         }
 
@@ -2,6 +2,7 @@ pub mod activity;
 pub mod call;
 pub mod condition;
 pub mod data;
+pub mod diagram;
 pub mod end;
 pub mod flow;
 pub mod input_requests;
@@ -1,6 +1,16 @@
-use serde::Deserialize;
+use std::time::Duration;
 
-use crate::steps::script::ScriptStep;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    steps::{
+        sandbox::{self, SandboxOverrides},
+        script::{InlineScript, ScriptModule, ScriptSource, ScriptStep, DEFAULT_SCRIPT_TIMEOUT},
+        script_engine::ScriptRuntime,
+    },
+    util,
+};
 
 use super::input_requests::InputRequests;
 
@@ -8,24 +18,187 @@ use super::input_requests::InputRequests;
 pub struct ScriptNode {
     #[serde(rename = "@id")]
     pub id: String,
+    #[serde(rename = "@runtime", default)]
+    pub runtime: ScriptRuntime,
     #[serde(rename = "@script")]
-    pub script: String,
+    pub script: Option<String>,
+    #[serde(rename = "Code")]
+    pub code: Option<String>,
     #[serde(rename = "Inputs")]
     pub inputs: InputRequests,
     #[serde(rename = "@input")]
     pub input: String,
     #[serde(rename = "@output")]
     pub output: String,
+    #[serde(rename = "@timeout", default, deserialize_with = "deserialize_timeout")]
+    pub timeout: Option<Duration>,
+    #[serde(rename = "Sandbox")]
+    pub sandbox: Option<SandboxNode>,
+    /// Outputs only depend on the inputs and are cached, see `ScriptCache`
+    #[serde(rename = "@pure", default)]
+    pub pure: bool,
+    /// The `Code` body, compiled by `compile`
+    #[serde(skip)]
+    pub inline: Option<InlineScript>,
+    /// Script root of the definition, set by `compile`
+    #[serde(skip)]
+    pub root: Option<String>,
+}
+
+/// Changes to the global sandbox policy for the script.
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+pub struct SandboxNode {
+    /// Comma separated modules allowed on top of the global policy
+    #[serde(rename = "@allowModules")]
+    pub allow_modules: Option<String>,
+    #[serde(rename = "@filesystem")]
+    pub filesystem: Option<bool>,
+    #[serde(rename = "@network")]
+    pub network: Option<bool>,
+    #[serde(
+        rename = "@maxMemory",
+        default,
+        deserialize_with = "sandbox::deserialize_memory"
+    )]
+    pub max_memory: Option<usize>,
+    #[serde(rename = "@maxCalls")]
+    pub max_calls: Option<u64>,
+    #[serde(rename = "@maxFuel")]
+    pub max_fuel: Option<u64>,
+}
+
+impl From<SandboxNode> for SandboxOverrides {
+    fn from(node: SandboxNode) -> Self {
+        SandboxOverrides {
+            allowed_modules: node
+                .allow_modules
+                .unwrap_or_default()
+                .split(',')
+                .map(|module| module.trim().to_string())
+                .filter(|module| !module.is_empty())
+                .collect(),
+            filesystem: node.filesystem,
+            network: node.network,
+            max_memory: node.max_memory,
+            max_calls: node.max_calls,
+            max_fuel: node.max_fuel,
+        }
+    }
+}
+
+fn deserialize_timeout<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    let timeout = String::deserialize(deserializer)?;
+
+    util::parse_duration(&timeout)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+impl ScriptNode {
+    /// Compiles the inline `Code` body, with line numbers relative to the definition `xml`.
+    /// Scripts are looked up in the script `root` of the definition first.
+    pub fn compile(&mut self, process_name: &str, root: Option<&str>, xml: &str) -> Result<()> {
+        self.root = root.map(|root| root.to_string());
+
+        if self.runtime == ScriptRuntime::Wasm {
+            return match (&self.script, &self.code) {
+                (Some(_), None) => Ok(()),
+                _ => Err(anyhow!(
+                    "Script node {} should have a script attribute, wasm scripts are modules",
+                    self.id
+                )),
+            };
+        }
+
+        let code = match (&self.script, &self.code) {
+            (Some(_), None) => return Ok(()),
+            (None, Some(code)) => code,
+            _ => {
+                return Err(anyhow!(
+                    "Script node {} should have either a script attribute or a Code body",
+                    self.id
+                ))
+            }
+        };
+
+        let (body, first_line) = match locate_code(xml, &self.id) {
+            Some((offset, body)) => (body, xml[..offset].matches('\n').count() + 1),
+            None => (code.clone(), 1),
+        };
+
+        let inline = InlineScript::compile(
+            format!("{}.{}", process_name, self.id),
+            format!("{}.ploy", process_name),
+            self.root.clone(),
+            &body,
+            first_line,
+        )
+        .map_err(|e| anyhow!("Script node {}: {}", self.id, e))?;
+
+        self.inline = Some(inline);
+
+        Ok(())
+    }
+}
+
+/// Offset and raw text of the `Code` body of the script node with the given id.
+fn locate_code(xml: &str, id: &str) -> Option<(usize, String)> {
+    let ids = [format!("id=\"{}\"", id), format!("id='{}'", id)];
+
+    let (node_start, node) = xml.match_indices("<ScriptNode").find_map(|(start, _)| {
+        let node = &xml[start..];
+        let tag = &node[..node.find('>')?];
+
+        ids.iter()
+            .any(|id| tag.contains(id.as_str()))
+            .then_some((start, node))
+    })?;
+
+    let node = &node[..node.find("</ScriptNode>")?];
+    let code_start = node.find("<Code>")? + "<Code>".len();
+    let code = &node[code_start..node.find("</Code>")?];
+
+    let cdata = code
+        .find("<![CDATA[")
+        .filter(|start| code[..*start].trim().is_empty());
+
+    match cdata {
+        Some(start) => {
+            let body_start = start + "<![CDATA[".len();
+            let body = &code[body_start..code.find("]]>")?];
+
+            Some((node_start + code_start + body_start, body.to_string()))
+        }
+        None => {
+            let body = quick_xml::escape::unescape(code).ok()?;
+
+            Some((node_start + code_start, body.into_owned()))
+        }
+    }
 }
 
 impl Into<ScriptStep> for ScriptNode {
     fn into(self) -> ScriptStep {
+        let module = ScriptModule::new(self.script.unwrap_or_default(), self.root);
+        let script = match (self.runtime, self.inline) {
+            (ScriptRuntime::Wasm, _) => ScriptSource::Wasm(module),
+            (ScriptRuntime::Python, Some(inline)) => ScriptSource::Inline(inline),
+            (ScriptRuntime::Python, None) => ScriptSource::Module(module),
+        };
+
         ScriptStep::new(
             self.id,
-            self.script,
+            script,
             self.input,
             self.output,
+            self.timeout.unwrap_or(DEFAULT_SCRIPT_TIMEOUT),
+            self.sandbox
+                .map(|sandbox| sandbox.into())
+                .unwrap_or_default(),
             self.inputs.into(),
         )
+        .with_pure(self.pure)
     }
 }
@@ -1,18 +1,33 @@
 use anyhow::Result;
 use quick_xml::de::from_str;
+use semver::Version;
 use serde::Deserialize;
 
+use crate::steps::script_paths;
+
 use super::{
-    nodes::{flow::FlowNodes, node::Nodes},
+    nodes::{
+        diagram::DiagramNodes,
+        flow::FlowNodes,
+        node::{NodeType, Nodes},
+    },
     process_definition::ProcessDefinition,
 };
 
 #[derive(Deserialize, PartialEq, Debug)]
 struct PloyDefinitionXml {
+    #[serde(rename = "@version")]
+    version: Option<Version>,
+    /// Directory within the scripts directories the scripts of the definition are looked up in
+    /// first
+    #[serde(rename = "@scripts")]
+    scripts: Option<String>,
     #[serde(rename = "Nodes")]
     nodes: Nodes,
     #[serde(rename = "Flow")]
     flow: FlowNodes,
+    #[serde(rename = "Diagram", default)]
+    diagram: DiagramNodes,
 }
 
 fn get_start_step(nodes: &Nodes) -> Result<String> {
@@ -20,7 +35,7 @@ fn get_start_step(nodes: &Nodes) -> Result<String> {
         .field
         .iter()
         .filter_map(|n| match n {
-            super::nodes::node::NodeType::StartNode(start) => Some(start.id.clone()),
+            NodeType::StartNode(start) => Some(start.id.clone()),
             _ => None,
         })
         .collect();
@@ -37,12 +52,31 @@ fn get_start_step(nodes: &Nodes) -> Result<String> {
         .ok_or_else(|| anyhow::anyhow!("No start node found in the process definition"))
 }
 
-pub fn parse_xml(xml: &str) -> Result<ProcessDefinition> {
-    let ploy: PloyDefinitionXml = from_str(xml)?;
+pub fn parse_xml(process_name: &str, xml: &str) -> Result<ProcessDefinition> {
+    let mut ploy: PloyDefinitionXml = from_str(xml)?;
+
+    if let Some(root) = &ploy.scripts {
+        script_paths::check_script_root(root).map_err(|e| anyhow::anyhow!(e))?;
+    }
+
+    for node in ploy.nodes.field.iter_mut() {
+        if let NodeType::ScriptNode(script) = node {
+            script.compile(process_name, ploy.scripts.as_deref(), xml)?;
+        }
+    }
 
+    let version = ploy.version.clone().unwrap_or(Version::new(1, 0, 0));
     let steps = ploy.nodes.clone().into();
     let flow = ploy.flow.clone().into();
     let start_step_id = get_start_step(&ploy.nodes)?;
+    let diagram = ploy.diagram.clone().into();
 
-    Ok(ProcessDefinition::new(steps, flow, start_step_id))
+    Ok(ProcessDefinition::new(
+        process_name.to_string(),
+        version,
+        steps,
+        flow,
+        start_step_id,
+        diagram,
+    ))
 }
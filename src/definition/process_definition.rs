@@ -1,26 +1,93 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
 
-use super::step::{FlowLeaf, Step};
+use jsonschema::JSONSchema;
+use log::warn;
+use semver::Version;
+
+use super::{
+    schema_registry::SchemaRegistry,
+    step::{FlowLeaf, Step},
+};
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct DiagramPosition {
+    pub id: String,
+    pub x: f64,
+    pub y: f64,
+}
 
 pub struct ProcessDefinition {
+    name: String,
+    version: Version,
     start_step_id: String,
     steps: HashMap<String, Box<dyn Step>>,
     flow: HashMap<String, Vec<FlowLeaf>>,
+    diagram: Vec<DiagramPosition>,
+    compiled_schemas: HashMap<String, Arc<JSONSchema>>,
 }
 
 impl ProcessDefinition {
     pub fn new(
+        name: String,
+        version: Version,
         steps: HashMap<String, Box<dyn Step>>,
         flow: HashMap<String, Vec<FlowLeaf>>,
         start_step_id: String,
+        diagram: Vec<DiagramPosition>,
     ) -> Self {
         Self {
+            name,
+            version,
             steps,
             flow,
             start_step_id,
+            diagram,
+            compiled_schemas: HashMap::default(),
         }
     }
 
+    /// Compiles input and output schemas of all steps, so that step data can be validated
+    /// without going through the schema registry. Missing schemas are reported by the validator.
+    pub fn compile_schemas(&mut self, schema_registry: &SchemaRegistry) {
+        for schema_name in self.get_schema_references() {
+            match schema_registry.get_compiled(&schema_name) {
+                Ok(compiled) => {
+                    self.compiled_schemas.insert(schema_name, compiled);
+                }
+                Err(err) => warn!(
+                    "Schema {} of process {} is not compiled: {}",
+                    schema_name, self.name, err
+                ),
+            }
+        }
+    }
+
+    /// Input and output schemas of all steps.
+    pub fn get_schema_references(&self) -> BTreeSet<String> {
+        self.steps
+            .values()
+            .flat_map(|step| [step.input_schema(), step.output_schema()])
+            .flatten()
+            .collect()
+    }
+
+    pub fn get_compiled_schema(&self, schema_name: &str) -> Option<&JSONSchema> {
+        self.compiled_schemas
+            .get(schema_name)
+            .map(|compiled| compiled.as_ref())
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_version(&self) -> &Version {
+        &self.version
+    }
+
     pub fn get_start_step_id(&self) -> String {
         self.start_step_id.clone()
     }
@@ -29,7 +96,15 @@ impl ProcessDefinition {
         self.steps.get(id)
     }
 
+    pub fn get_steps(&self) -> impl Iterator<Item = &dyn Step> {
+        self.steps.values().map(|step| step.as_ref())
+    }
+
     pub fn get_next(&self, id: &str) -> Option<&Vec<FlowLeaf>> {
         self.flow.get(id)
     }
+
+    pub fn get_diagram(&self) -> &[DiagramPosition] {
+        &self.diagram
+    }
 }
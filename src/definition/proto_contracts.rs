@@ -0,0 +1,355 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, Result};
+use log::info;
+use prost::Message;
+use prost_types::{
+    field_descriptor_proto::{Label, Type},
+    DescriptorProto, FieldDescriptorProto, FileDescriptorSet,
+};
+use semver::Version;
+use serde_json::{json, Map, Value};
+
+use super::schema_registry::SchemaRegistry;
+
+pub const PROTOS_PATH: &str = "data/protos";
+
+/// Prefix of schema names generated from protobuf messages, e.g. `proto:org.example.Message`.
+pub const PROTO_REFERENCE_PREFIX: &str = "proto:";
+
+const DESCRIPTOR_SET_EXTENSION: &str = "pb";
+
+const JSON_SCHEMA_DRAFT: &str = "http://json-schema.org/draft-04/schema#";
+
+const INT64_PATTERN: &str = "^-?[0-9]+$";
+
+/// Well-known types have a dedicated JSON mapping instead of the one of their fields.
+fn well_known_type_schema(type_name: &str) -> Option<Value> {
+    let schema = match type_name.strip_prefix("google.protobuf.")? {
+        "Timestamp" => json!({ "type": "string", "format": "date-time" }),
+        "Duration" => json!({ "type": "string", "pattern": "^-?[0-9]+(\\.[0-9]+)?s$" }),
+        "Struct" | "Empty" => json!({ "type": "object" }),
+        "Value" => json!({}),
+        "ListValue" => json!({ "type": "array" }),
+        "Any" => json!({ "type": "object", "required": ["@type"] }),
+        "FieldMask" | "StringValue" | "BytesValue" => json!({ "type": "string" }),
+        "DoubleValue" | "FloatValue" => json!({ "type": "number" }),
+        "Int64Value" | "UInt64Value" => {
+            json!({ "type": ["integer", "string"], "pattern": INT64_PATTERN })
+        }
+        "Int32Value" | "UInt32Value" => json!({ "type": "integer" }),
+        "BoolValue" => json!({ "type": "boolean" }),
+        _ => return None,
+    };
+
+    Some(schema)
+}
+
+pub fn schema_name(message_name: &str) -> String {
+    format!("{}{}", PROTO_REFERENCE_PREFIX, message_name)
+}
+
+fn to_json_name(field_name: &str) -> String {
+    let mut json_name = String::default();
+    let mut capitalize = false;
+
+    for c in field_name.chars() {
+        if c == '_' {
+            capitalize = true;
+        } else if capitalize {
+            json_name.extend(c.to_uppercase());
+            capitalize = false;
+        } else {
+            json_name.push(c);
+        }
+    }
+
+    json_name
+}
+
+/// Messages and enums of a descriptor set, by full name without the leading dot.
+#[derive(Default)]
+struct DescriptorPool {
+    messages: HashMap<String, DescriptorProto>,
+    enums: HashMap<String, Vec<(String, i32)>>,
+}
+
+impl DescriptorPool {
+    fn new(descriptor_set: &FileDescriptorSet) -> Self {
+        let mut pool = Self::default();
+
+        for file in descriptor_set.file.iter() {
+            let package = file.package();
+
+            for enum_type in file.enum_type.iter() {
+                pool.add_enum(package, enum_type);
+            }
+
+            for message in file.message_type.iter() {
+                pool.add_message(package, message);
+            }
+        }
+
+        pool
+    }
+
+    fn add_message(&mut self, scope: &str, message: &DescriptorProto) {
+        let full_name = Self::qualify(scope, message.name());
+
+        for enum_type in message.enum_type.iter() {
+            self.add_enum(&full_name, enum_type);
+        }
+
+        for nested_message in message.nested_type.iter() {
+            self.add_message(&full_name, nested_message);
+        }
+
+        self.messages.insert(full_name, message.clone());
+    }
+
+    fn add_enum(&mut self, scope: &str, enum_type: &prost_types::EnumDescriptorProto) {
+        let values = enum_type
+            .value
+            .iter()
+            .map(|value| (value.name().to_string(), value.number()))
+            .collect();
+
+        self.enums
+            .insert(Self::qualify(scope, enum_type.name()), values);
+    }
+
+    fn qualify(scope: &str, name: &str) -> String {
+        if scope.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", scope, name)
+        }
+    }
+
+    fn is_map_entry(message: &DescriptorProto) -> bool {
+        message
+            .options
+            .as_ref()
+            .is_some_and(|options| options.map_entry())
+    }
+
+    /// JSON Schema of the message following the proto3 JSON mapping. Other messages are
+    /// referenced by their schema name.
+    fn message_schema(&self, message_name: &str, message: &DescriptorProto) -> Result<Value> {
+        let mut properties = Map::default();
+        let mut required = Vec::default();
+        let mut oneofs: HashMap<i32, Vec<String>> = HashMap::default();
+
+        for field in message.field.iter() {
+            let json_name = match &field.json_name {
+                Some(json_name) => json_name.clone(),
+                None => to_json_name(field.name()),
+            };
+
+            let field_schema = self
+                .field_schema(field)
+                .map_err(|e| anyhow!("Field {}.{}: {}", message_name, field.name(), e))?;
+
+            if field.label() == Label::Required {
+                required.push(json!(json_name));
+            }
+
+            if let Some(oneof_index) = field.oneof_index {
+                if !field.proto3_optional() {
+                    oneofs
+                        .entry(oneof_index)
+                        .or_default()
+                        .push(json_name.clone());
+                }
+            }
+
+            properties.insert(json_name, field_schema);
+        }
+
+        let mut schema = Map::default();
+        schema.insert("$schema".to_string(), json!(JSON_SCHEMA_DRAFT));
+        schema.insert("title".to_string(), json!(message_name));
+        schema.insert("type".to_string(), json!("object"));
+        schema.insert("properties".to_string(), Value::Object(properties));
+        schema.insert("additionalProperties".to_string(), json!(false));
+
+        if !required.is_empty() {
+            schema.insert("required".to_string(), Value::Array(required));
+        }
+
+        // At most one field of a oneof may be set
+        let mut oneof_pairs = Vec::default();
+
+        for fields in oneofs.values() {
+            for (index, first) in fields.iter().enumerate() {
+                for second in fields.iter().skip(index + 1) {
+                    oneof_pairs.push(json!({ "required": [first, second] }));
+                }
+            }
+        }
+
+        if !oneof_pairs.is_empty() {
+            schema.insert("not".to_string(), json!({ "anyOf": oneof_pairs }));
+        }
+
+        Ok(Value::Object(schema))
+    }
+
+    fn field_schema(&self, field: &FieldDescriptorProto) -> Result<Value> {
+        let type_name = field.type_name().trim_start_matches('.');
+
+        let schema = match field.r#type() {
+            Type::Double | Type::Float => json!({ "type": "number" }),
+            Type::Int64 | Type::Uint64 | Type::Sint64 | Type::Fixed64 | Type::Sfixed64 => {
+                json!({ "type": ["integer", "string"], "pattern": INT64_PATTERN })
+            }
+            Type::Int32 | Type::Uint32 | Type::Sint32 | Type::Fixed32 | Type::Sfixed32 => {
+                json!({ "type": "integer" })
+            }
+            Type::Bool => json!({ "type": "boolean" }),
+            Type::String | Type::Bytes => json!({ "type": "string" }),
+            Type::Enum => {
+                let values = self
+                    .enums
+                    .get(type_name)
+                    .ok_or_else(|| anyhow!("Unknown enum {}", type_name))?;
+
+                let names = values.iter().map(|(name, _)| json!(name));
+                let numbers = values.iter().map(|(_, number)| json!(number));
+
+                json!({
+                    "type": ["string", "integer"],
+                    "enum": names.chain(numbers).collect::<Vec<Value>>(),
+                })
+            }
+            Type::Message | Type::Group => {
+                if let Some(schema) = well_known_type_schema(type_name) {
+                    schema
+                } else {
+                    let message = self.messages.get(type_name).ok_or_else(|| {
+                        anyhow!(
+                            "Unknown message {}, the descriptor set should include imports",
+                            type_name
+                        )
+                    })?;
+
+                    if Self::is_map_entry(message) {
+                        let value_field = message
+                            .field
+                            .iter()
+                            .find(|field| field.number() == 2)
+                            .ok_or_else(|| anyhow!("Map entry {} has no value", type_name))?;
+
+                        // Map keys are always strings in JSON
+                        return Ok(json!({
+                            "type": "object",
+                            "additionalProperties": self.field_schema(value_field)?,
+                        }));
+                    }
+
+                    json!({ "$ref": schema_name(type_name) })
+                }
+            }
+        };
+
+        if field.label() == Label::Repeated {
+            Ok(json!({ "type": "array", "items": schema }))
+        } else {
+            Ok(schema)
+        }
+    }
+}
+
+/// Adds a JSON Schema named `proto:{message}` for every message of the descriptor set, as
+/// produced by `protoc --include_imports --descriptor_set_out`. Messages registered before with
+/// the same definition are skipped.
+pub fn register_descriptor_set(
+    schema_registry: &SchemaRegistry,
+    descriptor_set: &[u8],
+) -> Result<Vec<String>> {
+    let descriptor_set = FileDescriptorSet::decode(descriptor_set)
+        .map_err(|e| anyhow!("Invalid descriptor set: {}", e))?;
+
+    let pool = DescriptorPool::new(&descriptor_set);
+
+    let mut message_names = pool
+        .messages
+        .iter()
+        .filter(|(name, message)| {
+            !DescriptorPool::is_map_entry(message) && well_known_type_schema(name).is_none()
+        })
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<String>>();
+
+    message_names.sort();
+
+    let mut schemas = Vec::default();
+
+    for message_name in message_names {
+        let schema = pool.message_schema(&message_name, &pool.messages[&message_name])?;
+        let name = schema_name(&message_name);
+
+        match schema_registry.get(&name) {
+            Ok(entry) if *entry.schema == schema => continue,
+            Ok(_) => return Err(anyhow!("Message {} is already registered", message_name)),
+            Err(_) => schemas.push((name, schema)),
+        }
+    }
+
+    let mut registered = Vec::default();
+
+    for (name, schema) in schemas {
+        schema_registry.add(&name, Version::new(1, 0, 0), schema)?;
+        registered.push(name);
+    }
+
+    Ok(registered)
+}
+
+/// Registers every `.pb` descriptor set of the directory, if it exists.
+pub fn load_descriptor_sets(schema_registry: &SchemaRegistry, directory: &str) -> Result<()> {
+    if !Path::new(directory).is_dir() {
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+
+        if path.extension().and_then(|e| e.to_str()) != Some(DESCRIPTOR_SET_EXTENSION) {
+            continue;
+        }
+
+        let descriptor_set = std::fs::read(&path)?;
+
+        register_descriptor_set(schema_registry, &descriptor_set)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+
+        info!("Descriptor set loaded: {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Stores the descriptor set in the protos directory and registers its messages.
+pub fn save_descriptor_set(
+    schema_registry: &SchemaRegistry,
+    name: &str,
+    descriptor_set: &[u8],
+) -> Result<Vec<String>> {
+    if name.is_empty() || name.contains(['/', '\\', '.']) {
+        return Err(anyhow!("Invalid descriptor set name '{}'", name));
+    }
+
+    let file_name = format!("{}/{}.{}", PROTOS_PATH, name, DESCRIPTOR_SET_EXTENSION);
+
+    if Path::new(&file_name).exists() {
+        return Err(anyhow!("Descriptor set {} already exists", name));
+    }
+
+    let registered = register_descriptor_set(schema_registry, descriptor_set)?;
+
+    std::fs::create_dir_all(PROTOS_PATH)?;
+    std::fs::write(file_name, descriptor_set)?;
+
+    Ok(registered)
+}
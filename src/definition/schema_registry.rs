@@ -0,0 +1,395 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{anyhow, Result};
+use jsonschema::{JSONSchema, SchemaResolver, SchemaResolverError};
+use log::{info, warn};
+use semver::{Version, VersionReq};
+use serde_json::Value;
+use url::Url;
+
+pub const SCHEMAS_PATH: &str = "data/schemas";
+
+const DEFAULT_SCHEMA_VERSION: Version = Version::new(1, 0, 0);
+
+type SchemaVersions = HashMap<String, BTreeMap<Version, Arc<Value>>>;
+
+#[derive(Debug, Clone)]
+pub struct SchemaEntry {
+    pub name: String,
+    pub version: Version,
+    pub schema: Arc<Value>,
+}
+
+/// Named and versioned JSON schemas known to the engine, along with their compiled form.
+///
+/// Schemas are referenced as `Name` for the latest version or `Name@requirement` for the latest
+/// version matching a semver requirement. The same references may be used in `$ref`, optionally
+/// suffixed with `.json` and followed by a JSON pointer fragment.
+#[derive(Debug)]
+pub struct SchemaRegistry {
+    directory: String,
+    schemas: Arc<RwLock<SchemaVersions>>,
+    compiled: RwLock<HashMap<(String, Version), Arc<JSONSchema>>>,
+}
+
+struct RegistryResolver {
+    schemas: Arc<RwLock<SchemaVersions>>,
+}
+
+impl SchemaResolver for RegistryResolver {
+    fn resolve(
+        &self,
+        _root_schema: &Value,
+        _url: &Url,
+        original_reference: &str,
+    ) -> Result<Arc<Value>, SchemaResolverError> {
+        let schemas = self
+            .schemas
+            .read()
+            .map_err(|_| anyhow!("Schema registry lock is poisoned"))?;
+
+        find_schema(&schemas, original_reference).map(|entry| entry.schema)
+    }
+}
+
+fn parse_reference(reference: &str) -> Result<(&str, VersionReq)> {
+    let reference = reference.split('#').next().unwrap_or_default();
+    let reference = reference.strip_suffix(".json").unwrap_or(reference);
+
+    match reference.split_once('@') {
+        Some((name, requirement)) => {
+            let requirement = VersionReq::parse(requirement)
+                .map_err(|e| anyhow!("Invalid version requirement '{}': {}", requirement, e))?;
+
+            Ok((name, requirement))
+        }
+        None => Ok((reference, VersionReq::STAR)),
+    }
+}
+
+fn find_schema(schemas: &SchemaVersions, reference: &str) -> Result<SchemaEntry> {
+    let (name, requirement) = parse_reference(reference)?;
+
+    let versions = schemas
+        .get(name)
+        .ok_or_else(|| anyhow!("Schema {} is not registered", name))?;
+
+    versions
+        .iter()
+        .rev()
+        .find(|(version, _)| requirement.matches(version))
+        .map(|(version, schema)| SchemaEntry {
+            name: name.to_string(),
+            version: version.clone(),
+            schema: schema.clone(),
+        })
+        .ok_or_else(|| anyhow!("Schema {} has no version matching {}", name, requirement))
+}
+
+/// Collects every `$ref` pointing outside of the schema document.
+fn collect_external_references(schema: &Value, references: &mut Vec<String>) {
+    match schema {
+        Value::Object(object) => {
+            for (key, value) in object {
+                match value {
+                    Value::String(reference) if key == "$ref" && !reference.starts_with('#') => {
+                        references.push(reference.clone())
+                    }
+                    _ => collect_external_references(value, references),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_external_references(item, references);
+            }
+        }
+        _ => {}
+    }
+}
+
+impl SchemaRegistry {
+    pub fn new(directory: &str) -> Self {
+        Self {
+            directory: directory.to_string(),
+            schemas: Arc::default(),
+            compiled: RwLock::default(),
+        }
+    }
+
+    /// Loads every `.json` schema from the directory. Files are named `{name}.json` for version
+    /// 1.0.0 or `{name}@{version}.json` for other versions.
+    pub fn load(directory: &str) -> Result<Self> {
+        let registry = Self::new(directory);
+
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+
+            let (name, version) = Self::parse_file_name(&path)?;
+            let schema = Self::load_schema(&path)?;
+
+            registry.add(&name, version, schema)?;
+        }
+
+        Ok(registry)
+    }
+
+    /// Logs schemas referencing schemas which are not registered.
+    pub fn verify_references(&self) {
+        for entry in self.list() {
+            if let Err(err) = self.check_references(&entry.schema) {
+                warn!("Schema {} {}: {}", entry.name, entry.version, err);
+            }
+        }
+    }
+
+    fn parse_file_name(path: &Path) -> Result<(String, Version)> {
+        let file_stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| anyhow!("Invalid schema file name {}", path.display()))?;
+
+        match file_stem.split_once('@') {
+            Some((name, version)) => {
+                let version = Version::parse(version)
+                    .map_err(|e| anyhow!("Invalid schema version {}: {}", path.display(), e))?;
+
+                Ok((name.to_string(), version))
+            }
+            None => Ok((file_stem.to_string(), DEFAULT_SCHEMA_VERSION)),
+        }
+    }
+
+    fn load_schema(path: &Path) -> Result<Value> {
+        let file_contents = std::fs::read_to_string(path)?;
+
+        serde_json::from_str(&file_contents)
+            .map_err(|e| anyhow!("Invalid schema {}: {}", path.display(), e))
+    }
+
+    fn read_schemas(&self) -> Result<std::sync::RwLockReadGuard<'_, SchemaVersions>> {
+        self.schemas
+            .read()
+            .map_err(|_| anyhow!("Schema registry lock is poisoned"))
+    }
+
+    fn write_schemas(&self) -> Result<std::sync::RwLockWriteGuard<'_, SchemaVersions>> {
+        self.schemas
+            .write()
+            .map_err(|_| anyhow!("Schema registry lock is poisoned"))
+    }
+
+    fn check_references(&self, schema: &Value) -> Result<()> {
+        let mut references = Vec::default();
+        collect_external_references(schema, &mut references);
+
+        let schemas = self.read_schemas()?;
+
+        for reference in references {
+            find_schema(&schemas, &reference)
+                .map_err(|e| anyhow!("Unresolved reference '{}': {}", reference, e))?;
+        }
+
+        Ok(())
+    }
+
+    fn compile(&self, schema: &Value) -> Result<JSONSchema> {
+        JSONSchema::options()
+            .with_resolver(RegistryResolver {
+                schemas: self.schemas.clone(),
+            })
+            .compile(schema)
+            .map_err(|e| anyhow!("Invalid schema: {}", e))
+    }
+
+    /// Adds a schema version to the registry without storing it. Registered versions are
+    /// immutable, so adding an existing version fails.
+    pub fn add(&self, name: &str, version: Version, schema: Value) -> Result<()> {
+        self.insert(name, version, schema, None)
+    }
+
+    /// Adds the schema version, storing it in `file_name` first when given so a schema that
+    /// could not be stored is not registered.
+    fn insert(
+        &self,
+        name: &str,
+        version: Version,
+        schema: Value,
+        file_name: Option<String>,
+    ) -> Result<()> {
+        if name.is_empty() || name.contains(['@', '/', '\\', '#']) {
+            return Err(anyhow!("Invalid schema name '{}'", name));
+        }
+
+        let mut schemas = self.write_schemas()?;
+
+        if schemas
+            .get(name)
+            .is_some_and(|versions| versions.contains_key(&version))
+        {
+            return Err(anyhow!("Schema {} {} is already registered", name, version));
+        }
+
+        if let Some(file_name) = file_name {
+            std::fs::write(file_name, serde_json::to_string_pretty(&schema)?)?;
+        }
+
+        info!("Schema loaded: {} {}", name, version);
+
+        schemas
+            .entry(name.to_string())
+            .or_default()
+            .insert(version, Arc::new(schema));
+
+        // References without a version requirement may now resolve to the new version
+        self.compiled
+            .write()
+            .map_err(|_| anyhow!("Schema registry lock is poisoned"))?
+            .clear();
+
+        Ok(())
+    }
+
+    /// Adds a new schema version after checking that it compiles and that its references
+    /// resolve, and stores it in the schemas directory.
+    pub fn register(&self, name: &str, version: Version, schema: Value) -> Result<()> {
+        self.compile(&schema)?;
+        self.check_references(&schema)?;
+
+        let file_name = if version == DEFAULT_SCHEMA_VERSION {
+            format!("{}/{}.json", self.directory, name)
+        } else {
+            format!("{}/{}@{}.json", self.directory, name, version)
+        };
+
+        self.insert(name, version, schema, Some(file_name))
+    }
+
+    /// Whether the reference, or any schema it resolves to through `$ref`, names one of the
+    /// given schemas, in any version.
+    pub fn refers_to(&self, reference: &str, names: &[String]) -> Result<bool> {
+        let schemas = self.read_schemas()?;
+        let mut references = vec![reference.to_string()];
+        let mut visited = HashSet::<String>::default();
+
+        while let Some(reference) = references.pop() {
+            let Ok((name, _)) = parse_reference(&reference) else {
+                continue;
+            };
+
+            if names.iter().any(|changed| changed == name) {
+                return Ok(true);
+            }
+
+            if !visited.insert(reference.clone()) {
+                continue;
+            }
+
+            if let Ok(entry) = find_schema(&schemas, &reference) {
+                collect_external_references(&entry.schema, &mut references);
+            }
+        }
+
+        Ok(false)
+    }
+
+    pub fn get(&self, reference: &str) -> Result<SchemaEntry> {
+        find_schema(&*self.read_schemas()?, reference)
+    }
+
+    pub fn get_compiled(&self, reference: &str) -> Result<Arc<JSONSchema>> {
+        let entry = self.get(reference)?;
+        let key = (entry.name, entry.version);
+
+        if let Some(compiled) = self
+            .compiled
+            .read()
+            .map_err(|_| anyhow!("Schema registry lock is poisoned"))?
+            .get(&key)
+        {
+            return Ok(compiled.clone());
+        }
+
+        let compiled = Arc::new(self.compile(&entry.schema)?);
+
+        self.compiled
+            .write()
+            .map_err(|_| anyhow!("Schema registry lock is poisoned"))?
+            .insert(key, compiled.clone());
+
+        Ok(compiled)
+    }
+
+    pub fn list(&self) -> Vec<SchemaEntry> {
+        let Ok(schemas) = self.read_schemas() else {
+            return vec![];
+        };
+
+        let mut entries = schemas
+            .iter()
+            .flat_map(|(name, versions)| {
+                versions.iter().map(|(version, schema)| SchemaEntry {
+                    name: name.clone(),
+                    version: version.clone(),
+                    schema: schema.clone(),
+                })
+            })
+            .collect::<Vec<SchemaEntry>>();
+
+        entries.sort_by(|a, b| a.name.cmp(&b.name).then(a.version.cmp(&b.version)));
+
+        entries
+    }
+
+    /// Replaces a `$ref` to another registered schema with the referenced schema, so that its
+    /// keywords can be inspected.
+    pub fn dereference(&self, schema: &Value) -> Result<Value> {
+        let Some(reference) = schema["$ref"].as_str() else {
+            return Ok(schema.clone());
+        };
+
+        if reference.starts_with('#') {
+            return Ok(schema.clone());
+        }
+
+        let entry = self.get(reference)?;
+
+        match reference.split_once('#') {
+            Some((_, pointer)) if !pointer.is_empty() => entry
+                .schema
+                .pointer(pointer)
+                .cloned()
+                .ok_or_else(|| anyhow!("Reference '{}' does not exist", reference)),
+            _ => Ok((*entry.schema).clone()),
+        }
+    }
+
+    pub fn validate(&self, value: &Value, reference: &str) -> Result<()> {
+        let compiled = self.get_compiled(reference)?;
+
+        Self::validate_compiled(&compiled, value)
+    }
+
+    pub fn validate_compiled(compiled: &JSONSchema, value: &Value) -> Result<()> {
+        let result = compiled.validate(value);
+
+        if let Err(errors) = result {
+            let err_message = errors
+                .map(|err| err.to_string())
+                .collect::<Vec<String>>()
+                .join("\n");
+
+            Err(anyhow!(err_message))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -1,20 +1,69 @@
+use std::{collections::BTreeMap, fmt, time::Duration};
+
 use anyhow::Result;
 use rustpython_vm::types::SelfIter;
-use serde_json::{Map, Value};
+use serde_json::{json, Map, Value};
+
+use crate::steps::{sandbox::SandboxOverrides, script::ScriptSource};
 
 pub type JobId = String;
 pub type StepOutputs = Map<String, Value>;
 
 pub trait ManageStep {
-    fn add_job(&self, job_name: String) -> JobId;
-    fn start_process(&self, process_name: String, inputs: Map<String, Value>) -> Result<JobId>;
+    fn add_job(&self, request: JobRequest) -> JobId;
+    fn start_process(&self, process: ProcessReference, inputs: Map<String, Value>)
+        -> Result<JobId>;
+    fn run_script(
+        &self,
+        script: ScriptSource,
+        timeout: Duration,
+        sandbox: SandboxOverrides,
+        pure: bool,
+    ) -> JobId;
     fn get_inputs(&self) -> &Map<String, Value>;
 }
 
+#[derive(PartialEq, Debug, Clone)]
+pub struct JobReference {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+impl JobReference {
+    pub fn new(name: String, version: Option<String>) -> Self {
+        Self { name, version }
+    }
+}
+
+/// Job added by an activity step.
+#[derive(PartialEq, Debug, Clone)]
+pub struct JobRequest {
+    pub job: JobReference,
+    pub output_schema: Option<String>,
+    /// The priority of the process when not set
+    pub priority: Option<i32>,
+    pub activity_name: Option<String>,
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ProcessReference {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+impl ProcessReference {
+    pub fn new(name: String, version: Option<String>) -> Self {
+        Self { name, version }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct FlowLeaf {
     pub to: String,
     pub input: Option<String>,
+    /// Followed only when the step fails
+    pub on_error: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -30,11 +79,49 @@ impl StepInputRequest {
     }
 }
 
+/// Why a step failed, e.g. the exception raised by a script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepError {
+    pub error_type: String,
+    pub message: String,
+    pub traceback: Option<String>,
+}
+
+impl StepError {
+    pub fn new(error_type: &str, message: String) -> Self {
+        Self {
+            error_type: error_type.to_string(),
+            message,
+            traceback: None,
+        }
+    }
+
+    pub fn with_traceback(mut self, traceback: String) -> Self {
+        self.traceback = Some(traceback);
+        self
+    }
+
+    pub fn to_value(&self) -> Value {
+        json!({
+            "type": self.error_type,
+            "message": self.message,
+            "traceback": self.traceback,
+        })
+    }
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.error_type, self.message)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum StepResult {
     AsyncJob(JobId),
     Completed(StepOutputs),
     ProcessEnded(StepOutputs),
+    Failed(StepError),
 }
 
 #[derive(Debug, Clone)]
@@ -42,6 +129,7 @@ pub enum StepExecutionStatus {
     Started,
     Waiting,
     Completed,
+    Failed,
 }
 
 #[derive(Debug, Clone)]
@@ -50,6 +138,7 @@ pub struct StepState {
     pub status: StepExecutionStatus,
     pub inputs: Map<String, Value>,
     pub outputs: Map<String, Value>,
+    pub error: Option<StepError>,
 }
 
 impl StepState {
@@ -59,6 +148,7 @@ impl StepState {
             status: StepExecutionStatus::Started,
             inputs: Map::default(),
             outputs: Map::default(),
+            error: None,
         }
     }
 }
@@ -95,6 +185,10 @@ pub trait Step: Send + Sync {
 
     fn get_type(&self) -> StepType;
 
+    fn name(&self) -> Option<String> {
+        None
+    }
+
     fn input_schema(&self) -> Option<String> {
         None
     }
@@ -103,6 +197,26 @@ pub trait Step: Send + Sync {
         None
     }
 
+    fn script(&self) -> Option<ScriptSource> {
+        None
+    }
+
+    fn sandbox(&self) -> Option<SandboxOverrides> {
+        None
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    fn job(&self) -> Option<JobReference> {
+        None
+    }
+
+    fn called_process(&self) -> Option<ProcessReference> {
+        None
+    }
+
     fn get_input_requests(&self) -> Vec<StepInputRequest> {
         vec![]
     }
@@ -110,7 +224,7 @@ pub trait Step: Send + Sync {
     fn get_next_steps(&self, _ctx: &dyn ManageStep, next_steps: &Vec<FlowLeaf>) -> Vec<String> {
         next_steps
             .iter()
-            .filter(|n| n.input.is_none())
+            .filter(|n| n.input.is_none() && !n.on_error)
             .map(|n| n.to.clone())
             .collect()
     }
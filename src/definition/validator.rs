@@ -1,8 +1,33 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, fmt, sync::Arc};
 
 use log::warn;
 
-use super::{process_definition::ProcessDefinition, step::StepInputRequest};
+use crate::steps::{sandbox::SandboxPolicy, script::ScriptSource, script_engine::ScriptEngines};
+
+use super::{
+    job_catalog::JobCatalog,
+    process_definition::ProcessDefinition,
+    schema_registry::SchemaRegistry,
+    step::{Step, StepInputRequest},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub step_id: String,
+    pub message: String,
+}
+
+impl ValidationError {
+    pub fn new(step_id: String, message: String) -> Self {
+        ValidationError { step_id, message }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.step_id, self.message)
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct IODescriptor {
@@ -23,35 +48,199 @@ impl IODescriptor {
 
 pub struct ProcessValidator {
     process_definition: Arc<ProcessDefinition>,
+    job_catalog: Arc<JobCatalog>,
+    schema_registry: Arc<SchemaRegistry>,
+    sandbox_policy: Arc<SandboxPolicy>,
     stack: HashMap<String, bool>,
     visited: HashMap<String, bool>,
+    errors: Vec<ValidationError>,
 }
 
 impl ProcessValidator {
-    fn new(process_definition: Arc<ProcessDefinition>) -> Self {
+    fn new(
+        process_definition: Arc<ProcessDefinition>,
+        job_catalog: Arc<JobCatalog>,
+        schema_registry: Arc<SchemaRegistry>,
+        sandbox_policy: Arc<SandboxPolicy>,
+    ) -> Self {
         let stack = HashMap::default();
         let visited = HashMap::default();
+        let errors = Vec::default();
 
         ProcessValidator {
             process_definition,
+            job_catalog,
+            schema_registry,
+            sandbox_policy,
             stack,
             visited,
+            errors,
         }
     }
 
-    pub fn validate(process_definition: Arc<ProcessDefinition>) -> bool {
-        Self::new(process_definition).validate_internal()
+    /// Validates the process definition, returning all found errors. An empty result means the
+    /// definition is valid.
+    pub fn validate(
+        process_definition: Arc<ProcessDefinition>,
+        job_catalog: Arc<JobCatalog>,
+        schema_registry: Arc<SchemaRegistry>,
+        sandbox_policy: Arc<SandboxPolicy>,
+    ) -> Vec<ValidationError> {
+        let mut validator = Self::new(
+            process_definition,
+            job_catalog,
+            schema_registry,
+            sandbox_policy,
+        );
+        validator.validate_internal();
+
+        validator.errors
     }
 
     fn validate_internal(&mut self) -> bool {
         let start_step = self.process_definition.get_start_step_id();
 
-        self.validate_step(&start_step)
+        let flow_valid = self.validate_step(&start_step);
+        let scripts_valid = self.validate_scripts();
+        let jobs_valid = self.validate_jobs();
+
+        flow_valid && scripts_valid && jobs_valid
+    }
+
+    fn report(&mut self, step_id: &str, message: String) {
+        warn!("{}", message);
+
+        self.errors
+            .push(ValidationError::new(step_id.to_string(), message));
+    }
+
+    fn validate_scripts(&mut self) -> bool {
+        let scripts = self
+            .process_definition
+            .get_steps()
+            .filter_map(|step| {
+                step.script().map(|script| {
+                    let policy = self
+                        .sandbox_policy
+                        .with_overrides(&step.sandbox().unwrap_or_default());
+
+                    (step.id(), script, policy)
+                })
+            })
+            .collect::<Vec<(String, ScriptSource, SandboxPolicy)>>();
+
+        if scripts.is_empty() {
+            return true;
+        }
+
+        let mut engines = ScriptEngines::default();
+        let mut valid = true;
+
+        for (step_id, script, policy) in scripts {
+            let result = engines.get(script.runtime()).check(&script, &policy);
+
+            if let Err(err) = result {
+                self.report(&step_id, err);
+                valid = false;
+            }
+        }
+
+        valid
+    }
+
+    fn validate_jobs(&mut self) -> bool {
+        let process_definition = self.process_definition.clone();
+        let mut valid = true;
+
+        for step in process_definition.get_steps() {
+            if step.job().is_some() && !self.check_job_specification(step) {
+                valid = false;
+            }
+        }
+
+        valid
+    }
+
+    fn check_job_specification(&mut self, step: &dyn Step) -> bool {
+        let step_id = step.id();
+        let job = step.job().expect("Step references a job");
+
+        let specification = match self.job_catalog.get(&job.name, job.version.as_deref()) {
+            Ok(specification) => specification,
+            Err(err) => {
+                self.report(
+                    &step_id,
+                    format!("Step {} job is unknown: {}", step_id, err),
+                );
+                return false;
+            }
+        };
+
+        let input_requests = step.get_input_requests();
+        let mut valid = true;
+
+        for input in specification.inputs.iter().filter(|input| !input.optional) {
+            if !input_requests
+                .iter()
+                .any(|input_request| input_request.name == input.name)
+            {
+                self.report(
+                    &step_id,
+                    format!(
+                        "Required input '{}' of job {} {} is missing in mapping of step {}",
+                        input.name, specification.name, specification.version, step_id
+                    ),
+                );
+                valid = false;
+            }
+        }
+
+        for input_request in input_requests.iter() {
+            let Some(input) = specification.get_input(&input_request.name) else {
+                self.report(
+                    &step_id,
+                    format!(
+                        "Input '{}' of step {} is not declared by job {} {}",
+                        input_request.name, step_id, specification.name, specification.version
+                    ),
+                );
+                valid = false;
+                continue;
+            };
+
+            let output_field = self
+                .process_definition
+                .get_step(&input_request.from)
+                .and_then(|from_step| from_step.output_schema())
+                .and_then(|output_schema| self.load_schema(&step_id, &output_schema))
+                .and_then(|output_schema| self.get_field(&output_schema, &input_request.output));
+
+            let expected_type = serde_json::Value::from(input.rtype.schema_type()).to_string();
+
+            if let Some(output_field) = output_field {
+                if output_field.rtype != expected_type {
+                    self.report(
+                        &step_id,
+                        format!(
+                            "Output field '{}' type {} does not match job {} input '{}' type {}",
+                            input_request.output,
+                            output_field.rtype,
+                            specification.name,
+                            input.name,
+                            expected_type
+                        ),
+                    );
+                    valid = false;
+                }
+            }
+        }
+
+        valid
     }
 
     fn validate_step(&mut self, step_id: &str) -> bool {
         if self.is_in_stack(step_id) {
-            warn!("Cycle detected: {}", step_id);
+            self.report(step_id, format!("Cycle detected: {}", step_id));
             return false;
         }
 
@@ -62,8 +251,9 @@ impl ProcessValidator {
         self.stack.insert(step_id.to_string(), true);
         self.visited.insert(step_id.to_string(), true);
 
-        let step = self.process_definition.get_step(step_id).unwrap();
-        let next_steps = self.process_definition.get_next(step_id);
+        let process_definition = self.process_definition.clone();
+        let step = process_definition.get_step(step_id).unwrap();
+        let next_steps = process_definition.get_next(step_id);
 
         let input_requests = step.get_input_requests();
 
@@ -79,7 +269,10 @@ impl ProcessValidator {
 
         if next_steps.is_none() || next_steps.unwrap().is_empty() {
             if !step.get_type().is_end() {
-                warn!("Last process step is not End: {}", step_id);
+                self.report(
+                    step_id,
+                    format!("Last process step is not End: {}", step_id),
+                );
                 return false;
             }
 
@@ -103,7 +296,7 @@ impl ProcessValidator {
         true
     }
 
-    fn check_missing_required_input_requests(&self, step_id: &str) -> bool {
+    fn check_missing_required_input_requests(&mut self, step_id: &str) -> bool {
         let step = self.process_definition.get_step(step_id).unwrap();
         let input_requests = step.get_input_requests();
 
@@ -116,30 +309,34 @@ impl ProcessValidator {
             return true;
         }
 
-        let input_schema = input_schema.unwrap();
-        let input_schema = Self::load_schema(&input_schema);
-
-        input_schema["required"]
-            .as_array()
-            .map(|required| {
-                for required_field in required {
-                    let required_field = required_field.as_str().unwrap();
-
-                    if !input_requests
+        let Some(input_schema) = self.load_schema(step_id, &input_schema.unwrap()) else {
+            return false;
+        };
+
+        let missing_field = input_schema["required"].as_array().and_then(|required| {
+            required
+                .iter()
+                .filter_map(|required_field| required_field.as_str())
+                .find(|required_field| {
+                    !input_requests
                         .iter()
-                        .any(|input_request| input_request.name == required_field)
-                    {
-                        warn!(
-                            "Required input field '{}' is missing in mapping of step {}",
-                            required_field, step_id
-                        );
-                        return false;
-                    }
-                }
+                        .any(|input_request| input_request.name == *required_field)
+                })
+                .map(|required_field| required_field.to_string())
+        });
+
+        if let Some(required_field) = missing_field {
+            self.report(
+                step_id,
+                format!(
+                    "Required input field '{}' is missing in mapping of step {}",
+                    required_field, step_id
+                ),
+            );
+            return false;
+        }
 
-                true
-            })
-            .unwrap_or(true)
+        true
     }
 
     fn is_in_stack(&self, step_id: &str) -> bool {
@@ -151,16 +348,23 @@ impl ProcessValidator {
     }
 
     // TODO? Optimize schema loading
-    fn check_input_request_conformance(&self, step_id: &str, request: &StepInputRequest) -> bool {
+    fn check_input_request_conformance(
+        &mut self,
+        step_id: &str,
+        request: &StepInputRequest,
+    ) -> bool {
         let step = self
             .process_definition
             .get_step(&request.from)
             .expect("Input should be mapped from existing step");
 
         if step.get_type().is_flow_step() && !self.is_in_stack(&request.from) {
-            warn!(
-                "Step {} should be executed before {} to map inputs from it",
-                request.from, step_id
+            self.report(
+                step_id,
+                format!(
+                    "Step {} should be executed before {} to map inputs from it",
+                    request.from, step_id
+                ),
             );
             return false;
         }
@@ -174,8 +378,9 @@ impl ProcessValidator {
             return true;
         }
 
-        let output_schema = output_schema.unwrap();
-        let output_schema = Self::load_schema(&output_schema);
+        let Some(output_schema) = self.load_schema(step_id, &output_schema.unwrap()) else {
+            return false;
+        };
 
         let request_step = self
             .process_definition
@@ -191,25 +396,32 @@ impl ProcessValidator {
             return true;
         }
 
-        let input_schema = input_schema.unwrap();
-        let input_schema = Self::load_schema(&input_schema);
+        let Some(input_schema) = self.load_schema(step_id, &input_schema.unwrap()) else {
+            return false;
+        };
 
-        let output_field = Self::get_field(&output_schema, &request.output);
-        let input_field = Self::get_field(&input_schema, &request.name);
+        let output_field = self.get_field(&output_schema, &request.output);
+        let input_field = self.get_field(&input_schema, &request.name);
 
         if output_field.is_none() {
-            warn!(
-                "Output field '{}' does not exist in schema {}",
-                request.output, output_schema
+            self.report(
+                step_id,
+                format!(
+                    "Output field '{}' does not exist in schema {}",
+                    request.output, output_schema
+                ),
             );
 
             return false;
         }
 
         if input_field.is_none() {
-            warn!(
-                "Input field '{}' does not exist in schema {}",
-                request.name, input_schema
+            self.report(
+                step_id,
+                format!(
+                    "Input field '{}' does not exist in schema {}",
+                    request.name, input_schema
+                ),
             );
 
             return false;
@@ -219,18 +431,24 @@ impl ProcessValidator {
         let input_field = input_field.unwrap();
 
         if output_field.rtype != input_field.rtype {
-            warn!(
-                "Output field '{}' type {} does not match input field {} type {}",
-                request.output, output_field.rtype, request.name, input_field.rtype
+            self.report(
+                step_id,
+                format!(
+                    "Output field '{}' type {} does not match input field {} type {}",
+                    request.output, output_field.rtype, request.name, input_field.rtype
+                ),
             );
 
             return false;
         }
 
         if input_field.required && !output_field.required {
-            warn!(
-                "Input field '{}' is required but output field '{}' is not",
-                request.name, request.output
+            self.report(
+                step_id,
+                format!(
+                    "Input field '{}' is required but output field '{}' is not",
+                    request.name, request.output
+                ),
             );
 
             return false;
@@ -239,11 +457,12 @@ impl ProcessValidator {
         true
     }
 
-    fn get_field(schema: &serde_json::Value, field_name: &str) -> Option<IODescriptor> {
+    fn get_field(&self, schema: &serde_json::Value, field_name: &str) -> Option<IODescriptor> {
         schema["properties"]
             .as_object()
             .and_then(|properties| properties.get(field_name))
-            .and_then(|field| {
+            .and_then(|field| self.schema_registry.dereference(field).ok())
+            .map(|field| {
                 let required = schema["required"]
                     .as_array()
                     .map(|required| {
@@ -251,18 +470,20 @@ impl ProcessValidator {
                     })
                     .unwrap_or(false);
 
-                Some(IODescriptor::new(
-                    field_name.to_string(),
-                    field["type"].to_string(),
-                    required,
-                ))
+                IODescriptor::new(field_name.to_string(), field["type"].to_string(), required)
             })
     }
 
-    fn load_schema(schema_name: &str) -> serde_json::Value {
-        let schema_contents =
-            std::fs::read_to_string(format!("data/schemas/{schema_name}.json")).unwrap();
-
-        return serde_json::from_str(&schema_contents).unwrap();
+    fn load_schema(&mut self, step_id: &str, schema_name: &str) -> Option<serde_json::Value> {
+        match self.schema_registry.get(schema_name) {
+            Ok(entry) => Some((*entry.schema).clone()),
+            Err(err) => {
+                self.report(
+                    step_id,
+                    format!("Step {} schema cannot be loaded: {}", step_id, err),
+                );
+                None
+            }
+        }
     }
 }
@@ -1,11 +1,21 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use actix::Addr;
 use serde_json::Map;
 use serde_json::Value;
 
-use crate::actors::engine_actor::{
-    EngineActor, GetProcessMessage, StartProcessMessage, ValidateProcessMessage,
+use crate::{
+    actors::engine_actor::{
+        DeployProcessMessage, EngineActor, GetDependencyGraphMessage, GetProcessMessage,
+        InvalidProcessDefinitionError, InvalidProcessNameError, LintProcessMessage,
+        RegisterDescriptorSetMessage, RegisterSchemaMessage, StartProcessMessage,
+        ValidateProcessMessage,
+    },
+    definition::{
+        linter::{LintConfig, LintRule},
+        schema_registry::SchemaRegistry,
+    },
+    steps::script_cache::ScriptCache,
 };
 
 pub mod engine {
@@ -14,11 +24,21 @@ pub mod engine {
 
 pub struct MyEngineService {
     engine: Addr<EngineActor>,
+    schema_registry: Arc<SchemaRegistry>,
+    script_cache: Arc<ScriptCache>,
 }
 
 impl MyEngineService {
-    pub fn new(engine: Addr<EngineActor>) -> Self {
-        Self { engine }
+    pub fn new(
+        engine: Addr<EngineActor>,
+        schema_registry: Arc<SchemaRegistry>,
+        script_cache: Arc<ScriptCache>,
+    ) -> Self {
+        Self {
+            engine,
+            schema_registry,
+            script_cache,
+        }
     }
 
     fn get_outputs(outputs: &Option<Map<String, Value>>) -> Value {
@@ -27,6 +47,20 @@ impl MyEngineService {
             None => Value::Null,
         }
     }
+
+    fn get_lint_config(request: &engine::ValidateProcessRequest) -> anyhow::Result<LintConfig> {
+        let mut config = LintConfig::default();
+
+        for rule in request.disabled_lint_rules.iter() {
+            config.disabled_rules.insert(rule.parse::<LintRule>()?);
+        }
+
+        if request.max_call_depth > 0 {
+            config.max_call_depth = request.max_call_depth as usize;
+        }
+
+        Ok(config)
+    }
 }
 
 #[tonic::async_trait]
@@ -38,6 +72,8 @@ impl engine::engine_service_server::EngineService for MyEngineService {
         let request = request.into_inner();
 
         let process_name = request.process_name;
+        let skip_validation = request.skip_validation;
+        let priority = request.priority;
 
         let data: HashMap<String, Result<Value, _>> = request
             .inputs
@@ -65,10 +101,23 @@ impl engine::engine_service_server::EngineService for MyEngineService {
                 job_id: None,
                 root_process_id: None,
                 process_name,
+                version: None,
+                skip_validation,
+                priority,
             })
             .await
             .map_err(|e| tonic::Status::internal(format!("Failed to start process: {}", e)))?
-            .map_err(|e| tonic::Status::internal(format!("Failed to start process: {}", e)))?;
+            .map_err(|e| {
+                if let Some(err) = e.downcast_ref::<InvalidProcessDefinitionError>() {
+                    return tonic::Status::failed_precondition(err.to_string());
+                }
+
+                if let Some(err) = e.downcast_ref::<InvalidProcessNameError>() {
+                    return tonic::Status::invalid_argument(err.to_string());
+                }
+
+                tonic::Status::internal(format!("Failed to start process: {}", e))
+            })?;
 
         Ok(tonic::Response::new(engine::StartProcessResponse {
             process_id,
@@ -92,6 +141,14 @@ impl engine::engine_service_server::EngineService for MyEngineService {
             id: process_context.process_id.clone(),
             status: process_context.state.to_string(),
             outputs: Self::get_outputs(&process_context.outputs).to_string(),
+            error: process_context
+                .error
+                .map(|process_error| engine::ProcessError {
+                    step_id: process_error.step_id,
+                    error_type: process_error.error.error_type,
+                    message: process_error.error.message,
+                    traceback: process_error.error.traceback.unwrap_or_default(),
+                }),
         }))
     }
 
@@ -99,17 +156,224 @@ impl engine::engine_service_server::EngineService for MyEngineService {
         &self,
         request: tonic::Request<engine::ValidateProcessRequest>,
     ) -> Result<tonic::Response<engine::ValidateProcessResponse>, tonic::Status> {
-        let valid = self
+        let request = request.into_inner();
+
+        let errors = self
             .engine
             .send(ValidateProcessMessage {
-                process_name: request.into_inner().process_name,
+                process_name: request.process_name.clone(),
             })
             .await
             .map_err(|e| tonic::Status::internal(format!("Failed to validate process: {}", e)))?
             .map_err(|e| tonic::Status::internal(format!("Failed to validate process: {}", e)))?;
 
+        let warnings = if request.lint {
+            let config = Self::get_lint_config(&request)
+                .map_err(|e| tonic::Status::invalid_argument(e.to_string()))?;
+
+            self.engine
+                .send(LintProcessMessage {
+                    process_name: request.process_name,
+                    config,
+                })
+                .await
+                .map_err(|e| tonic::Status::internal(format!("Failed to lint process: {}", e)))?
+                .map_err(|e| tonic::Status::internal(format!("Failed to lint process: {}", e)))?
+        } else {
+            vec![]
+        };
+
         Ok(tonic::Response::new(engine::ValidateProcessResponse {
-            valid,
+            valid: errors.is_empty(),
+            errors: errors.iter().map(|e| e.to_string()).collect(),
+            warnings: warnings.iter().map(|w| w.to_string()).collect(),
+        }))
+    }
+
+    async fn deploy_process(
+        &self,
+        request: tonic::Request<engine::DeployProcessRequest>,
+    ) -> Result<tonic::Response<engine::DeployProcessResponse>, tonic::Status> {
+        let request = request.into_inner();
+
+        let process_definition =
+            crate::definition::parser::parse_xml(&request.process_name, &request.definition)
+                .map_err(|e| {
+                    tonic::Status::invalid_argument(format!("Invalid process definition: {}", e))
+                })?;
+
+        let errors = self
+            .engine
+            .send(DeployProcessMessage {
+                process_name: request.process_name,
+                process_definition,
+                source: request.definition,
+            })
+            .await
+            .map_err(|e| tonic::Status::internal(format!("Failed to deploy process: {}", e)))?
+            .map_err(|e| match e.downcast_ref::<InvalidProcessNameError>() {
+                Some(err) => tonic::Status::invalid_argument(err.to_string()),
+                None => tonic::Status::internal(format!("Failed to deploy process: {}", e)),
+            })?;
+
+        Ok(tonic::Response::new(engine::DeployProcessResponse {
+            valid: errors.is_empty(),
+            errors: errors.iter().map(|e| e.to_string()).collect(),
+        }))
+    }
+
+    async fn get_dependency_graph(
+        &self,
+        request: tonic::Request<engine::GetDependencyGraphRequest>,
+    ) -> Result<tonic::Response<engine::GetDependencyGraphResponse>, tonic::Status> {
+        let process_name = request.into_inner().process_name;
+
+        let dependency_graph = self
+            .engine
+            .send(GetDependencyGraphMessage)
+            .await
+            .map_err(|e| tonic::Status::internal(format!("Failed to get dependencies: {}", e)))?
+            .map_err(|e| tonic::Status::internal(format!("Failed to get dependencies: {}", e)))?;
+
+        let affected_processes = if process_name.is_empty() {
+            vec![]
+        } else {
+            dependency_graph.get_dependents(&process_name)
+        };
+
+        Ok(tonic::Response::new(engine::GetDependencyGraphResponse {
+            processes: dependency_graph.get_processes(),
+            dependencies: dependency_graph
+                .get_dependencies()
+                .into_iter()
+                .map(|dependency| engine::ProcessDependency {
+                    process: dependency.process,
+                    step_id: dependency.step_id,
+                    called_process: dependency.called_process,
+                    version: dependency.version.unwrap_or_default(),
+                })
+                .collect(),
+            affected_processes,
+            cycles: dependency_graph
+                .get_cycles()
+                .iter()
+                .map(|cycle| cycle.processes.join(" -> "))
+                .collect(),
+        }))
+    }
+
+    async fn register_schema(
+        &self,
+        request: tonic::Request<engine::RegisterSchemaRequest>,
+    ) -> Result<tonic::Response<engine::RegisterSchemaResponse>, tonic::Status> {
+        let request = request.into_inner();
+
+        let version = if request.version.is_empty() {
+            semver::Version::new(1, 0, 0)
+        } else {
+            semver::Version::parse(&request.version).map_err(|e| {
+                tonic::Status::invalid_argument(format!("Invalid schema version: {}", e))
+            })?
+        };
+
+        let schema: Value = serde_json::from_str(&request.schema)
+            .map_err(|e| tonic::Status::invalid_argument(format!("Invalid schema JSON: {}", e)))?;
+
+        self.engine
+            .send(RegisterSchemaMessage {
+                name: request.name,
+                version,
+                schema,
+            })
+            .await
+            .map_err(|e| tonic::Status::internal(format!("Failed to register schema: {}", e)))?
+            .map_err(|e| {
+                tonic::Status::invalid_argument(format!("Failed to register schema: {}", e))
+            })?;
+
+        Ok(tonic::Response::new(engine::RegisterSchemaResponse {}))
+    }
+
+    async fn get_schema(
+        &self,
+        request: tonic::Request<engine::GetSchemaRequest>,
+    ) -> Result<tonic::Response<engine::GetSchemaResponse>, tonic::Status> {
+        let request = request.into_inner();
+
+        let reference = if request.version.is_empty() {
+            request.name
+        } else {
+            format!("{}@{}", request.name, request.version)
+        };
+
+        let entry = self
+            .schema_registry
+            .get(&reference)
+            .map_err(|e| tonic::Status::not_found(e.to_string()))?;
+
+        Ok(tonic::Response::new(engine::GetSchemaResponse {
+            name: entry.name,
+            version: entry.version.to_string(),
+            schema: entry.schema.to_string(),
         }))
     }
+
+    async fn list_schemas(
+        &self,
+        _request: tonic::Request<engine::ListSchemasRequest>,
+    ) -> Result<tonic::Response<engine::ListSchemasResponse>, tonic::Status> {
+        Ok(tonic::Response::new(engine::ListSchemasResponse {
+            schemas: self
+                .schema_registry
+                .list()
+                .into_iter()
+                .map(|entry| engine::SchemaVersion {
+                    name: entry.name,
+                    version: entry.version.to_string(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_script_cache_stats(
+        &self,
+        _request: tonic::Request<engine::GetScriptCacheStatsRequest>,
+    ) -> Result<tonic::Response<engine::GetScriptCacheStatsResponse>, tonic::Status> {
+        let stats = self.script_cache.stats();
+
+        Ok(tonic::Response::new(engine::GetScriptCacheStatsResponse {
+            hits: stats.hits,
+            misses: stats.misses,
+            inserts: stats.inserts,
+            evictions: stats.evictions,
+            skipped: stats.skipped,
+            entries: stats.entries as u64,
+            size: stats.size as u64,
+        }))
+    }
+
+    async fn register_descriptor_set(
+        &self,
+        request: tonic::Request<engine::RegisterDescriptorSetRequest>,
+    ) -> Result<tonic::Response<engine::RegisterDescriptorSetResponse>, tonic::Status> {
+        let request = request.into_inner();
+
+        let schemas = self
+            .engine
+            .send(RegisterDescriptorSetMessage {
+                name: request.name,
+                descriptor_set: request.descriptor_set,
+            })
+            .await
+            .map_err(|e| {
+                tonic::Status::internal(format!("Failed to register descriptor set: {}", e))
+            })?
+            .map_err(|e| {
+                tonic::Status::invalid_argument(format!("Failed to register descriptor set: {}", e))
+            })?;
+
+        Ok(tonic::Response::new(
+            engine::RegisterDescriptorSetResponse { schemas },
+        ))
+    }
 }
@@ -2,25 +2,76 @@ pub mod jobworker {
     tonic::include_proto!("org.xapik.ploy.jobworker");
 }
 
+use std::{sync::Arc, time::UNIX_EPOCH};
+
 use actix::Addr;
 use serde_json::{Map, Value};
 use tonic::Response;
 
-use crate::actors::job_worker_actor::{GetWorkItems, JobCompletedMessage, JobWorkerActor};
+use crate::{
+    actors::job_worker_actor::{
+        CompleteWorkItem, FailWorkItem, GetWorkItems, InvalidJobOutputsError, InvalidJobStateError,
+        JobNotFoundError, JobWorkerActor, StaleJobAttemptError,
+    },
+    definition::{
+        job_catalog::{JobCatalog, JobParameter},
+        step::StepError,
+    },
+};
 
 use self::jobworker::{
     job_worker_service_server::JobWorkerService, CompleteWorkItemRequest, CompleteWorkItemResponse,
+    FailWorkItemRequest, FailWorkItemResponse, JobType, ListJobTypesRequest, ListJobTypesResponse,
     WorkItem, WorkRequest, WorkResponse,
 };
 
+/// Error type of jobs a worker failed without giving one.
+pub const JOB_FAILED: &str = "JobFailed";
+
 #[derive(Debug)]
 pub struct MyJobWorkerService {
     job_worker_actor: Addr<JobWorkerActor>,
+    job_catalog: Arc<JobCatalog>,
 }
 
 impl MyJobWorkerService {
-    pub fn new(job_worker_actor: Addr<JobWorkerActor>) -> Self {
-        MyJobWorkerService { job_worker_actor }
+    pub fn new(job_worker_actor: Addr<JobWorkerActor>, job_catalog: Arc<JobCatalog>) -> Self {
+        MyJobWorkerService {
+            job_worker_actor,
+            job_catalog,
+        }
+    }
+
+    fn to_job_parameters(parameters: &[JobParameter]) -> Vec<jobworker::JobParameter> {
+        parameters
+            .iter()
+            .map(|parameter| jobworker::JobParameter {
+                name: parameter.name.clone(),
+                r#type: parameter.rtype.to_string(),
+                optional: parameter.optional,
+            })
+            .collect()
+    }
+
+    fn to_status(err: anyhow::Error) -> tonic::Status {
+        if let Some(err) = err.downcast_ref::<JobNotFoundError>() {
+            return tonic::Status::not_found(err.to_string());
+        }
+
+        if let Some(err) = err.downcast_ref::<InvalidJobStateError>() {
+            return tonic::Status::failed_precondition(err.to_string());
+        }
+
+        if let Some(err) = err.downcast_ref::<StaleJobAttemptError>() {
+            return tonic::Status::failed_precondition(err.to_string());
+        }
+
+        if let Some(err) = err.downcast_ref::<InvalidJobOutputsError>() {
+            return tonic::Status::invalid_argument(err.to_string());
+        }
+
+        log::error!("Error: {:?}", err);
+        tonic::Status::internal("Internal error")
     }
 }
 
@@ -38,19 +89,60 @@ impl JobWorkerService for MyJobWorkerService {
                 tonic::Status::invalid_argument("Invalid outputs JSON")
             })?;
 
-        let message = JobCompletedMessage::new(inner_request.job_id, job_outputs);
-        self.job_worker_actor.do_send(message);
+        self.job_worker_actor
+            .send(CompleteWorkItem {
+                job_id: inner_request.job_id,
+                attempt: Some(inner_request.attempt).filter(|attempt| *attempt != 0),
+                outputs: job_outputs,
+                idempotency_key: Some(inner_request.idempotency_key).filter(|key| !key.is_empty()),
+            })
+            .await
+            .map_err(|e| {
+                log::error!("Error: {:?}", e);
+                tonic::Status::internal("Internal error")
+            })?
+            .map_err(Self::to_status)?;
 
         Ok(Response::new(CompleteWorkItemResponse {}))
     }
 
+    async fn fail_work_item(
+        &self,
+        request: tonic::Request<FailWorkItemRequest>,
+    ) -> Result<tonic::Response<FailWorkItemResponse>, tonic::Status> {
+        let inner_request = request.into_inner();
+
+        let error_type = match inner_request.error_type.as_str() {
+            "" => JOB_FAILED,
+            error_type => error_type,
+        };
+
+        self.job_worker_actor
+            .send(FailWorkItem {
+                job_id: inner_request.job_id,
+                attempt: Some(inner_request.attempt).filter(|attempt| *attempt != 0),
+                error: StepError::new(error_type, inner_request.message),
+                idempotency_key: Some(inner_request.idempotency_key).filter(|key| !key.is_empty()),
+            })
+            .await
+            .map_err(|e| {
+                log::error!("Error: {:?}", e);
+                tonic::Status::internal("Internal error")
+            })?
+            .map_err(Self::to_status)?;
+
+        Ok(Response::new(FailWorkItemResponse {}))
+    }
+
     async fn get_work_items(
         &self,
-        _request: tonic::Request<WorkRequest>,
+        request: tonic::Request<WorkRequest>,
     ) -> Result<tonic::Response<WorkResponse>, tonic::Status> {
         let job_items = self
             .job_worker_actor
-            .send(GetWorkItems)
+            .send(GetWorkItems {
+                job_names: request.into_inner().job_names,
+            })
             .await
             .map_err(|e| {
                 log::error!("Error: {:?}", e);
@@ -64,10 +156,40 @@ impl JobWorkerService for MyJobWorkerService {
                     job_id: i.id,
                     inputs: i.inputs,
                     job_name: i.job_name,
+                    process_id: i.owner.process_id,
+                    root_process_id: i.owner.root_process_id,
+                    step_id: i.owner.step_id,
+                    activity_name: i.metadata.activity_name.unwrap_or_default(),
+                    definition_name: i.metadata.definition_name,
+                    definition_version: i.metadata.definition_version,
+                    attempt: i.attempts,
+                    lease_deadline: i
+                        .lease_deadline
+                        .and_then(|deadline| deadline.duration_since(UNIX_EPOCH).ok())
+                        .map_or(0, |deadline| deadline.as_millis() as i64),
+                    headers: i.metadata.headers.into_iter().collect(),
                 })
                 .collect(),
         };
 
         Ok(Response::new(response))
     }
+    async fn list_job_types(
+        &self,
+        _request: tonic::Request<ListJobTypesRequest>,
+    ) -> Result<tonic::Response<ListJobTypesResponse>, tonic::Status> {
+        let job_types = self
+            .job_catalog
+            .list()
+            .into_iter()
+            .map(|specification| JobType {
+                name: specification.name.clone(),
+                version: specification.version.to_string(),
+                inputs: Self::to_job_parameters(&specification.inputs),
+                outputs: Self::to_job_parameters(&specification.outputs),
+            })
+            .collect();
+
+        Ok(Response::new(ListJobTypesResponse { job_types }))
+    }
 }
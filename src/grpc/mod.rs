@@ -1,2 +1,2 @@
-pub mod job_worker_service;
 pub mod engine_service;
+pub mod job_worker_service;
@@ -0,0 +1,6 @@
+pub mod actors;
+pub mod definition;
+pub mod grpc;
+pub mod steps;
+pub mod types;
+pub mod util;
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use actix::{Actor, Arbiter};
 use actix_rt::System;
 
@@ -5,34 +7,88 @@ use log::info;
 use tokio::select;
 use tonic::transport::Server;
 
-use crate::grpc::{
-    engine_service::{engine::engine_service_server::EngineServiceServer, MyEngineService},
-    job_worker_service::{
-        jobworker::job_worker_service_server::JobWorkerServiceServer, MyJobWorkerService,
+use ploy_engine::{
+    actors,
+    definition::{
+        job_catalog::JobCatalog,
+        proto_contracts,
+        schema_registry::{self, SchemaRegistry},
+    },
+    grpc::{
+        engine_service::{engine::engine_service_server::EngineServiceServer, MyEngineService},
+        job_worker_service::{
+            jobworker::job_worker_service_server::JobWorkerServiceServer, MyJobWorkerService,
+        },
+    },
+    steps::{
+        sandbox::{self, CountingAllocator, SandboxPolicy},
+        script_cache::ScriptCache,
+        script_paths::ScriptPaths,
+        script_tests::{self, ScriptTestRunner},
     },
+    types,
 };
 
-pub mod actors;
-pub mod definition;
-pub mod grpc;
-pub mod steps;
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
 
 #[actix_rt::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     log4rs::init_file("log4rs.yaml", Default::default()).unwrap();
 
+    let script_paths = ScriptPaths::from_env();
+    script_paths.check()?;
+    script_paths.install();
+
+    let job_catalog = Arc::new(JobCatalog::load("data/jobs")?);
+    let schema_registry = SchemaRegistry::load(schema_registry::SCHEMAS_PATH)?;
+    types::load_types(&schema_registry, types::TYPES_PATH)?;
+    proto_contracts::load_descriptor_sets(&schema_registry, proto_contracts::PROTOS_PATH)?;
+    schema_registry.verify_references();
+    let schema_registry = Arc::new(schema_registry);
+    let sandbox_policy = Arc::new(SandboxPolicy::load(sandbox::SANDBOX_POLICY_PATH)?);
+
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if args.first().map(|arg| arg.as_str()) == Some("test-scripts") {
+        return run_script_tests(&args[1..], schema_registry, sandbox_policy);
+    }
+
+    let script_cache = Arc::new(ScriptCache::from_env()?);
+
     let arbiter_handle = Arbiter::current();
-    let job_worker_actor = actors::job_worker_actor::JobWorkerActor::default().start();
-    let engine_actor =
-        actors::engine_actor::EngineActor::new(arbiter_handle.clone(), job_worker_actor.clone())
-            .start();
+    let job_worker_actor = actors::job_worker_actor::JobWorkerActor::new(
+        actors::job_store::JobRetention::from_env()?,
+        actors::job_store::lease_from_env()?,
+        job_catalog.clone(),
+        schema_registry.clone(),
+    )
+    .start();
+    let script_worker_actor = actors::script_worker_actor::ScriptWorkerActor::start_workers(
+        std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+        sandbox_policy.clone(),
+        script_cache.clone(),
+    );
+    let engine_actor = actors::engine_actor::EngineActor::new(
+        arbiter_handle.clone(),
+        job_worker_actor.clone(),
+        script_worker_actor,
+        job_catalog.clone(),
+        schema_registry.clone(),
+        sandbox_policy,
+    )
+    .start();
 
     let addr = "0.0.0.0:50051".parse()?;
     let t1 = Server::builder()
         .add_service(JobWorkerServiceServer::new(MyJobWorkerService::new(
             job_worker_actor,
+            job_catalog,
+        )))
+        .add_service(EngineServiceServer::new(MyEngineService::new(
+            engine_actor,
+            schema_registry,
+            script_cache,
         )))
-        .add_service(EngineServiceServer::new(MyEngineService::new(engine_actor)))
         .serve(addr);
 
     let t2 = actix_rt::signal::ctrl_c();
@@ -52,3 +108,43 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// `test-scripts [directory] [--report file]` runs the script test suites and writes a JSON
+/// report, to stdout without a report file.
+fn run_script_tests(
+    args: &[String],
+    schema_registry: Arc<SchemaRegistry>,
+    sandbox_policy: Arc<SandboxPolicy>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut directory = script_tests::SCRIPT_TESTS_PATH;
+    let mut report_file = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--report" => {
+                report_file = Some(args.next().ok_or("--report needs a file name")?.as_str())
+            }
+            _ => directory = arg,
+        }
+    }
+
+    let report = ScriptTestRunner::new(
+        schema_registry,
+        sandbox_policy,
+        actors::engine_actor::PROCESS_DEFINITIONS_PATH,
+    )
+    .run_directory(directory)?;
+    let json = serde_json::to_string_pretty(&report)?;
+
+    match report_file {
+        Some(report_file) => std::fs::write(report_file, json)?,
+        None => println!("{json}"),
+    }
+
+    if report.failed > 0 {
+        return Err(format!("{} script tests failed", report.failed).into());
+    }
+
+    Ok(())
+}
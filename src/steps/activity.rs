@@ -1,24 +1,61 @@
+use std::collections::BTreeMap;
+
 use anyhow::Result;
+use log::warn;
+use serde_json::{Map, Value};
+
+use crate::definition::step::{
+    JobReference, JobRequest, ManageStep, Step, StepInputRequest, StepResult,
+};
+
+/// Priority of the job an activity adds, static or read from an input.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActivityPriority {
+    pub value: Option<i32>,
+    pub input: Option<String>,
+}
+
+impl ActivityPriority {
+    /// None when the job should have the priority of its process.
+    pub fn resolve(&self, inputs: &Map<String, Value>) -> Option<i32> {
+        let Some(input) = &self.input else {
+            return self.value;
+        };
 
-use crate::definition::step::{ManageStep, Step, StepInputRequest, StepResult};
+        let Some(value) = inputs.get(input) else {
+            return self.value;
+        };
+
+        match value.as_i64() {
+            Some(priority) => Some(priority.clamp(i32::MIN.into(), i32::MAX.into()) as i32),
+            None => {
+                warn!("Priority input '{}' is not a number: {}", input, value);
+                self.value
+            }
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct ActivityStep {
     id: String,
-    name: String,
-    job: String,
+    name: Option<String>,
+    job: JobReference,
     input_schema: String,
     output_schema: String,
     inputs: Vec<StepInputRequest>,
+    priority: ActivityPriority,
+    /// Handed to the workers with the job
+    headers: BTreeMap<String, String>,
 }
 
 impl ActivityStep {
     pub fn new(
         id: String,
-        name: String,
+        name: Option<String>,
         input_schema: String,
         output_schema: String,
-        job: String,
+        job: JobReference,
         inputs: Vec<StepInputRequest>,
     ) -> Self {
         Self {
@@ -28,8 +65,20 @@ impl ActivityStep {
             output_schema,
             job,
             inputs,
+            priority: ActivityPriority::default(),
+            headers: BTreeMap::default(),
         }
     }
+
+    pub fn with_priority(mut self, priority: ActivityPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_headers(mut self, headers: BTreeMap<String, String>) -> Self {
+        self.headers = headers;
+        self
+    }
 }
 
 impl Step for ActivityStep {
@@ -37,6 +86,10 @@ impl Step for ActivityStep {
         self.id.clone()
     }
 
+    fn name(&self) -> Option<String> {
+        self.name.clone()
+    }
+
     fn input_schema(&self) -> Option<String> {
         Some(self.input_schema.clone())
     }
@@ -49,8 +102,18 @@ impl Step for ActivityStep {
         self.inputs.clone()
     }
 
+    fn job(&self) -> Option<JobReference> {
+        Some(self.job.clone())
+    }
+
     fn start(&self, ctx: &dyn ManageStep) -> Result<StepResult> {
-        let job_id = ctx.add_job(self.job.clone());
+        let job_id = ctx.add_job(JobRequest {
+            job: self.job.clone(),
+            output_schema: Some(self.output_schema.clone()),
+            priority: self.priority.resolve(ctx.get_inputs()),
+            activity_name: self.name.clone(),
+            headers: self.headers.clone(),
+        });
 
         Ok(StepResult::AsyncJob(job_id))
     }
@@ -1,13 +1,13 @@
-use crate::definition::step::{Step, StepInputRequest, StepResult};
+use crate::definition::step::{ProcessReference, Step, StepInputRequest, StepResult};
 
 pub struct CallStep {
     pub id: String,
-    pub process: String,
+    pub process: ProcessReference,
     pub inputs: Vec<StepInputRequest>,
 }
 
 impl CallStep {
-    pub fn new(id: String, process: String, inputs: Vec<StepInputRequest>) -> Self {
+    pub fn new(id: String, process: ProcessReference, inputs: Vec<StepInputRequest>) -> Self {
         Self {
             id,
             process,
@@ -25,6 +25,10 @@ impl Step for CallStep {
         self.inputs.clone()
     }
 
+    fn called_process(&self) -> Option<ProcessReference> {
+        Some(self.process.clone())
+    }
+
     fn start(
         &self,
         ctx: &dyn crate::definition::step::ManageStep,
@@ -33,7 +37,7 @@ impl Step for CallStep {
 
         Ok(StepResult::AsyncJob(job_id))
     }
-    
+
     fn get_type(&self) -> crate::definition::step::StepType {
         crate::definition::step::StepType::CallStep
     }
@@ -30,6 +30,7 @@ impl Step for ConditionStep {
 
         let steps: Vec<String> = next_steps
             .iter()
+            .filter(|n| !n.on_error)
             .filter(|n| {
                 if let Some(input) = &n.input {
                     inputs.contains_key(input)
@@ -45,7 +46,7 @@ impl Step for ConditionStep {
         if steps.is_empty() {
             next_steps
                 .iter()
-                .filter(|n| n.input.is_none())
+                .filter(|n| n.input.is_none() && !n.on_error)
                 .map(|n| n.to.clone())
                 .collect()
         } else {
@@ -0,0 +1,549 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicIsize, AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use rustpython::vm::{
+    builtins::{PyBaseExceptionRef, PyCode, PyDict, PyStr},
+    bytecode::CodeObject,
+    compiler::{self, CompileOpts, Mode},
+    py_serde::{deserialize, serialize},
+    signal::UserSignalSender,
+    AsObject, Interpreter, PyObjectRef, PyRef, PyResult, VirtualMachine,
+};
+use serde_json::{value::Serializer, Map, Value};
+
+use crate::definition::step::StepError;
+
+use super::{
+    ploy_module::{self, ScriptContext, PLOY_MODULE},
+    sandbox::{self, SandboxPolicy, SANDBOX_VIOLATION},
+    script::{create_interruptible_interpreter, ScriptSource},
+    script_engine::{CONTRACT_VIOLATION, SCRIPT_NOT_FOUND},
+    script_paths::{self, script_paths},
+};
+
+/// Executions after which a pooled interpreter is replaced, so anything a script manages to
+/// leave behind in shared modules and goes unnoticed does not live forever.
+const MAX_EXECUTIONS: usize = 1000;
+
+/// Interval at which a timed out script is interrupted again. The script may catch the
+/// exception, and the flag telling interpreters to look for interrupts is shared by all of them,
+/// so another busy interpreter can clear it first.
+const INTERRUPT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// What a cached code object was compiled from, to tell when it is outdated.
+enum ScriptOrigin {
+    File(Option<SystemTime>),
+    Inline(Arc<CodeObject>),
+}
+
+impl ScriptOrigin {
+    fn is_same(&self, other: &ScriptOrigin) -> bool {
+        match (self, other) {
+            (ScriptOrigin::File(modified), ScriptOrigin::File(other)) => modified == other,
+            (ScriptOrigin::Inline(code), ScriptOrigin::Inline(other)) => Arc::ptr_eq(code, other),
+            _ => false,
+        }
+    }
+}
+
+/// Run of an interpreter its watchdog looks after.
+struct WatchedRun {
+    run: u64,
+    started: Instant,
+    timeout: Option<Duration>,
+    max_memory: Option<usize>,
+}
+
+/// Thread raising a `TimeoutError` in the watched run once its timeout elapsed, or a
+/// `SandboxViolation` once it allocated more than the memory budget. It lives as long as its
+/// interpreter and is armed again for every run.
+struct Watchdog {
+    // A run to watch, or `None` once it finished
+    runs: mpsc::Sender<Option<WatchedRun>>,
+}
+
+impl Watchdog {
+    /// Watches the runs of the interpreter of the calling thread.
+    fn start(current_run: Arc<AtomicU64>, interrupt: UserSignalSender) -> Self {
+        let memory_usage = sandbox::memory_usage();
+        let (runs, receiver) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            Self::watch_runs(receiver, current_run, interrupt, memory_usage)
+        });
+
+        Self { runs }
+    }
+
+    /// Watches the run until the returned guard is dropped.
+    fn watch(&self, run: u64, timeout: Option<Duration>, max_memory: Option<usize>) -> WatchGuard {
+        // The thread only stops with the sender
+        let _ = self.runs.send(Some(WatchedRun {
+            run,
+            started: Instant::now(),
+            timeout,
+            max_memory,
+        }));
+
+        WatchGuard {
+            runs: self.runs.clone(),
+        }
+    }
+
+    /// Interrupts left over from a previous run are ignored.
+    fn watch_runs(
+        receiver: mpsc::Receiver<Option<WatchedRun>>,
+        current_run: Arc<AtomicU64>,
+        interrupt: UserSignalSender,
+        memory_usage: &AtomicIsize,
+    ) {
+        let mut watched: Option<WatchedRun> = None;
+        let mut wait = INTERRUPT_INTERVAL;
+
+        loop {
+            let received = match watched {
+                Some(_) => receiver.recv_timeout(wait),
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            match received {
+                Ok(run) => {
+                    wait = match &run {
+                        Some(WatchedRun {
+                            timeout: Some(timeout),
+                            max_memory: None,
+                            ..
+                        }) => *timeout,
+                        _ => INTERRUPT_INTERVAL,
+                    };
+                    watched = run;
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => return,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            let Some(WatchedRun {
+                run,
+                started,
+                timeout,
+                max_memory,
+            }) = watched
+            else {
+                continue;
+            };
+
+            let timed_out = timeout.is_some_and(|timeout| started.elapsed() >= timeout);
+            let out_of_memory = max_memory.is_some_and(|max_memory| {
+                memory_usage.load(Ordering::SeqCst) > max_memory as isize
+            });
+
+            wait = match timeout {
+                Some(timeout) if !timed_out && max_memory.is_none() => {
+                    timeout.saturating_sub(started.elapsed())
+                }
+                _ => INTERRUPT_INTERVAL,
+            };
+
+            if !timed_out && !out_of_memory {
+                continue;
+            }
+
+            let current_run = current_run.clone();
+
+            let sent = interrupt.send(Box::new(move |vm| {
+                if current_run.load(Ordering::SeqCst) != run {
+                    return Ok(());
+                }
+
+                match timeout {
+                    Some(timeout) if timed_out => Err(vm.new_exception_msg(
+                        vm.ctx.exceptions.timeout_error.to_owned(),
+                        format!("Script exceeded its timeout of {} ms", timeout.as_millis()),
+                    )),
+                    _ => sandbox::check_budgets(vm),
+                }
+            }));
+
+            if sent.is_err() {
+                watched = None;
+            }
+        }
+    }
+}
+
+/// Tells the watchdog that the run finished.
+struct WatchGuard {
+    runs: mpsc::Sender<Option<WatchedRun>>,
+}
+
+impl Drop for WatchGuard {
+    fn drop(&mut self) {
+        let _ = self.runs.send(None);
+    }
+}
+
+/// Ids of the objects bound in the dict of a module, by name.
+type ModuleFingerprint = HashMap<String, usize>;
+
+struct CompiledScript {
+    origin: ScriptOrigin,
+    code: PyRef<PyCode>,
+    imports: Vec<String>,
+}
+
+/// Interpreter with the standard library and `pre-import` already loaded.
+///
+/// The standard library and builtins are shared by the runs of the interpreter. It is replaced
+/// after a run that rebound a name in one of their modules, e.g. `json.dumps = ...`, but changes
+/// to the attributes of classes or functions, or to a module the run imported first, are only
+/// dropped after `MAX_EXECUTIONS` runs.
+struct PooledInterpreter {
+    // Declared before the interpreter so code objects are dropped first
+    scripts: HashMap<String, CompiledScript>,
+    warm_modules: HashSet<String>,
+    shared_modules: HashMap<String, ModuleFingerprint>,
+    changed_shared_modules: bool,
+    scripts_directory: PathBuf,
+    executions: usize,
+    current_run: Arc<AtomicU64>,
+    interrupt: UserSignalSender,
+    watchdog: Watchdog,
+    interpreter: Interpreter,
+}
+
+impl PooledInterpreter {
+    fn new() -> Self {
+        let (interpreter, interrupt) = create_interruptible_interpreter();
+
+        let warm_modules = interpreter.enter(|vm| {
+            Self::sys_modules(vm)
+                .map(|modules| {
+                    modules
+                        .into_iter()
+                        .filter_map(|(name, _)| Self::module_name(&name))
+                        .collect()
+                })
+                .unwrap_or_default()
+        });
+
+        let mut shared_modules = HashMap::default();
+        interpreter.enter(|vm| Self::check_shared_modules(vm, &mut shared_modules));
+
+        let current_run = Arc::new(AtomicU64::new(0));
+        let watchdog = Watchdog::start(current_run.clone(), interrupt.clone());
+
+        PooledInterpreter {
+            scripts: HashMap::default(),
+            warm_modules,
+            shared_modules,
+            changed_shared_modules: false,
+            scripts_directory: script_paths().scripts.clone(),
+            executions: 0,
+            current_run,
+            interrupt,
+            watchdog,
+            interpreter,
+        }
+    }
+
+    fn sys_modules(vm: &VirtualMachine) -> PyResult<PyRef<PyDict>> {
+        vm.sys_module
+            .get_attr("modules", vm)?
+            .downcast::<PyDict>()
+            .map_err(|_| vm.new_type_error("sys.modules is not a dict".to_string()))
+    }
+
+    fn module_name(name: &PyObjectRef) -> Option<String> {
+        name.downcast_ref::<PyStr>()
+            .map(|name| name.as_str().to_string())
+    }
+
+    fn execute(
+        &mut self,
+        script_source: &ScriptSource,
+        input: Value,
+        timeout: Option<Duration>,
+        policy: &SandboxPolicy,
+        context: &ScriptContext,
+    ) -> Result<Map<String, Value>, StepError> {
+        self.executions += 1;
+
+        let script = script_source.name();
+        // Modules are cached by file, as definitions may have their own module of the same name
+        let (key, path, origin) = match script_source {
+            ScriptSource::Module(module) => {
+                let path = module
+                    .file(self.scripts_directory.as_path(), "py")
+                    .to_string_lossy()
+                    .to_string();
+                let modified = std::fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok();
+
+                (path.clone(), path, ScriptOrigin::File(modified))
+            }
+            ScriptSource::Inline(inline_script) => (
+                inline_script.name.clone(),
+                inline_script.path.clone(),
+                ScriptOrigin::Inline(inline_script.code.clone()),
+            ),
+            ScriptSource::Wasm(module) => {
+                return Err(StepError::new(
+                    SCRIPT_NOT_FOUND,
+                    format!("Script '{}' is not a Python script", module.name),
+                ))
+            }
+        };
+
+        let run = self.current_run.fetch_add(1, Ordering::SeqCst) + 1;
+        let watchdog = (timeout.is_some() || policy.max_memory.is_some())
+            .then(|| self.watchdog.watch(run, timeout, policy.max_memory));
+        let interrupt = self.interrupt.clone();
+        let scripts = &mut self.scripts;
+        let warm_modules = &self.warm_modules;
+        let shared_modules = &mut self.shared_modules;
+        let changed_shared_modules = &mut self.changed_shared_modules;
+        let scripts_directory = &self.scripts_directory;
+
+        self.interpreter.enter(|vm| {
+            let cached = scripts
+                .get(&key)
+                .is_some_and(|compiled| compiled.origin.is_same(&origin));
+
+            if !cached {
+                let code = match &origin {
+                    ScriptOrigin::File(_) => {
+                        let source = std::fs::read_to_string(&path).map_err(|e| {
+                            StepError::new(
+                                SCRIPT_NOT_FOUND,
+                                format!(
+                                    "Script module '{script}' can not be read from {path}: {e}"
+                                ),
+                            )
+                        })?;
+
+                        compiler::compile(&source, Mode::Exec, path.clone(), CompileOpts::default())
+                            .map_err(|e| {
+                                Self::exception_error(vm, &vm.new_syntax_error(&e, Some(&source)))
+                            })?
+                    }
+                    ScriptOrigin::Inline(code) => code.as_ref().clone(),
+                };
+
+                sandbox::check_attributes(&code)
+                    .map_err(|message| StepError::new(SANDBOX_VIOLATION, message))?;
+
+                let imports = sandbox::imported_modules(&code);
+
+                scripts.insert(
+                    key.clone(),
+                    CompiledScript {
+                        origin,
+                        code: vm.ctx.new_code(code),
+                        imports,
+                    },
+                );
+            }
+
+            let compiled = &scripts[&key];
+
+            policy
+                .check_imports(&compiled.imports)
+                .map_err(|message| StepError::new(SANDBOX_VIOLATION, message))?;
+
+            let code = compiled.code.clone();
+            let result = ploy_module::run_with_context(vm, context, || {
+                script_paths::run_in_root(vm, script_source.root(), || {
+                    sandbox::run_sandboxed(vm, policy, Some(interrupt), || {
+                        Self::run(vm, script, &path, code, input)
+                    })
+                })
+            });
+            drop(watchdog);
+
+            Self::unload_script_modules(vm, warm_modules, scripts_directory);
+            *changed_shared_modules = Self::check_shared_modules(vm, shared_modules);
+
+            let result = result.map_err(|err| Self::exception_error(vm, &err))?;
+
+            if !result.payload_is::<PyDict>() {
+                return Err(StepError::new(
+                    CONTRACT_VIOLATION,
+                    format!(
+                        "Script '{script}' returned {} instead of a dict",
+                        result.class().name()
+                    ),
+                ));
+            }
+
+            match serialize(vm, &result, Serializer) {
+                Ok(Value::Object(outputs)) => Ok(outputs),
+                Ok(_) => unreachable!("A dict is serialized to an object"),
+                Err(e) => Err(StepError::new(
+                    CONTRACT_VIOLATION,
+                    format!("Script '{script}' returned a dict that is not JSON: {e}"),
+                )),
+            }
+        })
+    }
+
+    fn exception_error(vm: &VirtualMachine, err: &PyBaseExceptionRef) -> StepError {
+        let message = err
+            .as_object()
+            .str(vm)
+            .map(|message| message.as_str().to_string())
+            .unwrap_or_default();
+
+        let mut traceback = String::new();
+        let _ = vm.write_exception(&mut traceback, err);
+
+        StepError::new(&err.class().name(), message).with_traceback(traceback)
+    }
+
+    /// Runs the module code in a fresh scope, so globals never leak from one run to the next.
+    /// Dates and decimals of the result are converted to JSON friendly values.
+    fn run(
+        vm: &VirtualMachine,
+        script: &str,
+        path: &str,
+        code: PyRef<PyCode>,
+        input: Value,
+    ) -> PyResult {
+        let scope = vm.new_scope_with_builtins();
+        scope
+            .globals
+            .set_item("__name__", vm.new_pyobj(script), vm)?;
+        scope.globals.set_item("__file__", vm.new_pyobj(path), vm)?;
+
+        vm.run_code_obj(code, scope.clone())?;
+
+        let execute_fn = scope.globals.get_item("execute", vm)?;
+        let py_input = deserialize(vm, input).map_err(|e| vm.new_value_error(e.to_string()))?;
+
+        let result = execute_fn.call((py_input,), vm)?;
+
+        ploy_module::to_json(vm, result)
+    }
+
+    /// Modules imported by scripts from the scripts directory are dropped after every run, the
+    /// standard library stays loaded.
+    fn unload_script_modules(
+        vm: &VirtualMachine,
+        warm_modules: &HashSet<String>,
+        scripts_directory: &Path,
+    ) {
+        let Ok(modules) = Self::sys_modules(vm) else {
+            return;
+        };
+
+        let script_modules = (&*modules)
+            .into_iter()
+            .filter(|(name, module)| {
+                Self::module_name(name).is_some_and(|name| !warm_modules.contains(&name))
+                    && module
+                        .get_attr("__file__", vm)
+                        .ok()
+                        .and_then(|file| file.downcast_ref::<PyStr>().map(|f| f.to_string()))
+                        .is_some_and(|file| Path::new(&file).starts_with(scripts_directory))
+            })
+            .map(|(name, _)| name)
+            .collect::<Vec<PyObjectRef>>();
+
+        for name in script_modules {
+            let _ = modules.del_item(&*name, vm);
+        }
+    }
+
+    fn fingerprint(module: &PyObjectRef) -> ModuleFingerprint {
+        module
+            .dict()
+            .map(|dict| {
+                dict.into_iter()
+                    .filter_map(|(name, value)| Some((Self::module_name(&name)?, value.get_id())))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether a name of a module shared by the runs was rebound since the module was first
+    /// seen. Modules of the scripts directory are already unloaded, `ploy` changes every run.
+    fn check_shared_modules(
+        vm: &VirtualMachine,
+        shared_modules: &mut HashMap<String, ModuleFingerprint>,
+    ) -> bool {
+        let Ok(modules) = Self::sys_modules(vm) else {
+            return true;
+        };
+
+        let mut changed = false;
+
+        for (name, module) in &*modules {
+            let Some(name) = Self::module_name(&name).filter(|name| name != PLOY_MODULE) else {
+                continue;
+            };
+
+            let fingerprint = Self::fingerprint(&module);
+
+            match shared_modules.get(&name) {
+                Some(shared) => changed |= *shared != fingerprint,
+                None => {
+                    shared_modules.insert(name, fingerprint);
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+thread_local! {
+    static INTERPRETER: RefCell<Option<PooledInterpreter>> = const { RefCell::new(None) };
+}
+
+/// Creates the interpreter of the current thread ahead of the first script execution.
+pub fn warm_up() {
+    INTERPRETER.with(|cell| {
+        cell.borrow_mut().get_or_insert_with(PooledInterpreter::new);
+    });
+}
+
+/// Calls `execute(input)` of the script module on the interpreter of the current thread,
+/// creating it on first use. Compiled scripts are cached until the file or definition changes.
+pub fn execute_script(
+    script: &ScriptSource,
+    input: Value,
+    timeout: Option<Duration>,
+    policy: &SandboxPolicy,
+    context: &ScriptContext,
+) -> Result<Map<String, Value>, StepError> {
+    INTERPRETER.with(|cell| {
+        let mut pooled = cell.borrow_mut();
+
+        let expired = match pooled.as_ref() {
+            Some(interpreter) => {
+                interpreter.executions >= MAX_EXECUTIONS || interpreter.changed_shared_modules
+            }
+            None => true,
+        };
+
+        if expired {
+            *pooled = None;
+            *pooled = Some(PooledInterpreter::new());
+        }
+
+        pooled
+            .as_mut()
+            .expect("Interpreter created")
+            .execute(script, input, timeout, policy, context)
+    })
+}
@@ -3,5 +3,13 @@ pub mod call;
 pub mod condition;
 pub mod data;
 pub mod end;
+pub mod interpreter_pool;
+pub mod ploy_module;
+pub mod sandbox;
 pub mod script;
+pub mod script_cache;
+pub mod script_engine;
+pub mod script_paths;
+pub mod script_tests;
 pub mod start;
+pub mod wasm_engine;
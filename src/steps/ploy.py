@@ -0,0 +1,78 @@
+"""Helpers of the `ploy` module, the context attributes and `_log` are set by the engine."""
+
+
+class _Logger:
+    """Writes to the engine log, with the process and step of the script."""
+
+    def debug(self, message, **fields):
+        _log("debug", str(message), fields)
+
+    def info(self, message, **fields):
+        _log("info", str(message), fields)
+
+    def warning(self, message, **fields):
+        _log("warning", str(message), fields)
+
+    def error(self, message, **fields):
+        _log("error", str(message), fields)
+
+
+log = _Logger()
+
+
+def now():
+    """Current time in UTC."""
+    from datetime import datetime, timezone
+
+    return datetime.now(timezone.utc)
+
+
+def parse_date(value):
+    """Date from an ISO 8601 string such as `2024-01-31`."""
+    from datetime import date
+
+    return date.fromisoformat(value)
+
+
+def parse_datetime(value):
+    """Date and time from an ISO 8601 string, `Z` is accepted for UTC."""
+    from datetime import datetime
+
+    if value.endswith("Z"):
+        value = value[:-1] + "+00:00"
+
+    return datetime.fromisoformat(value)
+
+
+def decimal(value):
+    """Exact decimal of a string or number, floats are converted from their shortest repr."""
+    import decimal
+
+    if isinstance(value, float):
+        value = repr(value)
+
+    return decimal.Decimal(value)
+
+
+def to_json(value):
+    """Converts dates to ISO 8601 strings and decimals to strings, so they keep their
+    precision, in dicts, lists and tuples."""
+    import sys
+
+    if isinstance(value, dict):
+        return {key: to_json(item) for key, item in value.items()}
+
+    if isinstance(value, (list, tuple)):
+        return [to_json(item) for item in value]
+
+    datetime = sys.modules.get("datetime")
+
+    if datetime is not None and isinstance(value, (datetime.date, datetime.time)):
+        return value.isoformat()
+
+    decimal = sys.modules.get("decimal")
+
+    if decimal is not None and isinstance(value, decimal.Decimal):
+        return str(value)
+
+    return value
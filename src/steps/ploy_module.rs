@@ -0,0 +1,170 @@
+use std::cell::RefCell;
+
+use log::{log, Level};
+use rustpython::vm::{
+    builtins::{PyDict, PyMappingProxy, PyModule, PyStr},
+    py_serde::{deserialize, serialize},
+    scope::Scope,
+    PyObjectRef, PyPayload, PyRef, PyResult, VirtualMachine,
+};
+use serde_json::{value::Serializer, Map, Value};
+
+/// Name under which scripts import the module.
+pub const PLOY_MODULE: &str = "ploy";
+
+const PLOY_SOURCE: &str = include_str!("ploy.py");
+
+const LOG_TARGET: &str = "ploy::script";
+
+/// What a script run knows about the process it runs in, exposed by the `ploy` module.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScriptContext {
+    pub process_id: String,
+    pub step_id: String,
+    pub definition_name: String,
+    pub definition_version: String,
+    /// Inputs the process was started with
+    pub variables: Map<String, Value>,
+}
+
+thread_local! {
+    static CURRENT_CONTEXT: RefCell<Option<ScriptContext>> = const { RefCell::new(None) };
+}
+
+/// Registers the `ploy` module, without context until a script runs with one.
+pub fn install(vm: &VirtualMachine) -> PyResult<()> {
+    let module = vm.new_module(PLOY_MODULE, vm.ctx.new_dict(), None);
+    let globals = module.dict();
+
+    globals.set_item("_log", vm.new_function("_log", log_message).into(), vm)?;
+    set_context(vm, &module, None)?;
+
+    vm.run_code_string(
+        Scope::with_builtins(None, globals, vm),
+        PLOY_SOURCE,
+        format!("<{PLOY_MODULE}>"),
+    )?;
+
+    vm.sys_module
+        .get_attr("modules", vm)?
+        .set_item(PLOY_MODULE, module.into(), vm)
+}
+
+fn module(vm: &VirtualMachine) -> PyResult<PyRef<PyModule>> {
+    vm.sys_module
+        .get_attr("modules", vm)?
+        .get_item(PLOY_MODULE, vm)?
+        .downcast::<PyModule>()
+        .map_err(|_| vm.new_type_error(format!("sys.modules['{PLOY_MODULE}'] is not a module")))
+}
+
+fn set_context(
+    vm: &VirtualMachine,
+    module: &PyRef<PyModule>,
+    context: Option<&ScriptContext>,
+) -> PyResult<()> {
+    let globals = module.dict();
+    let text = |value: Option<&String>| match value {
+        Some(value) => vm.new_pyobj(value.as_str()),
+        None => vm.ctx.none(),
+    };
+
+    globals.set_item("process_id", text(context.map(|c| &c.process_id)), vm)?;
+    globals.set_item("step_id", text(context.map(|c| &c.step_id)), vm)?;
+    globals.set_item(
+        "definition_name",
+        text(context.map(|c| &c.definition_name)),
+        vm,
+    )?;
+    globals.set_item(
+        "definition_version",
+        text(context.map(|c| &c.definition_version)),
+        vm,
+    )?;
+
+    let variables = context
+        .map(|context| Value::Object(context.variables.clone()))
+        .unwrap_or_else(|| Value::Object(Map::default()));
+    let variables = deserialize(vm, variables)
+        .map_err(|e| vm.new_value_error(e.to_string()))?
+        .downcast::<PyDict>()
+        .map_err(|_| vm.new_type_error("Process variables are not a dict".to_string()))?;
+
+    globals.set_item(
+        "variables",
+        PyMappingProxy::from(variables).into_ref(&vm.ctx).into(),
+        vm,
+    )
+}
+
+/// Runs `f` with the context exposed by the `ploy` module.
+pub fn run_with_context<R>(
+    vm: &VirtualMachine,
+    context: &ScriptContext,
+    f: impl FnOnce() -> PyResult<R>,
+) -> PyResult<R> {
+    let module = module(vm)?;
+
+    set_context(vm, &module, Some(context))?;
+    CURRENT_CONTEXT.with(|current| *current.borrow_mut() = Some(context.clone()));
+
+    let result = f();
+
+    CURRENT_CONTEXT.with(|current| *current.borrow_mut() = None);
+    set_context(vm, &module, None)?;
+
+    result
+}
+
+/// Converts dates and decimals in the value returned by a script with `ploy.to_json`.
+pub fn to_json(vm: &VirtualMachine, value: PyObjectRef) -> PyResult {
+    module(vm)?.get_attr("to_json", vm)?.call((value,), vm)
+}
+
+fn log_message(
+    level: PyRef<PyStr>,
+    message: PyRef<PyStr>,
+    fields: PyObjectRef,
+    vm: &VirtualMachine,
+) {
+    let level = match level.as_str() {
+        "debug" => Level::Debug,
+        "warning" => Level::Warn,
+        "error" => Level::Error,
+        _ => Level::Info,
+    };
+
+    let fields = match serialize(vm, &fields, Serializer) {
+        Ok(Value::Object(fields)) if !fields.is_empty() => format!(" {}", Value::Object(fields)),
+        Ok(_) => String::default(),
+        Err(_) => match fields.repr(vm) {
+            Ok(fields) => format!(" {}", fields.as_str()),
+            Err(_) => String::default(),
+        },
+    };
+
+    CURRENT_CONTEXT.with(|current| {
+        write_log(
+            current.borrow().as_ref(),
+            level,
+            &format!("{}{}", message.as_str(), fields),
+        )
+    });
+}
+
+/// Writes a message of a script to the engine log, under its process and step when known.
+pub fn write_log(context: Option<&ScriptContext>, level: Level, message: &str) {
+    match context {
+        Some(context) => log!(
+            target: LOG_TARGET,
+            level,
+            "[{} {}:{} {}] {}",
+            context.process_id,
+            context.definition_name,
+            context.definition_version,
+            context.step_id,
+            message
+        ),
+        None => log!(target: LOG_TARGET, level, "{}", message),
+    }
+}
@@ -0,0 +1,715 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicIsize, Ordering},
+    time::SystemTime,
+};
+
+use anyhow::Result;
+use log::info;
+use rustpython::vm::{
+    builtins::{PyBaseExceptionRef, PyStr, PyType},
+    bytecode::{BorrowedConstant, CodeObject, Constant, Instruction, OpArgState},
+    compiler::{self, CompileOpts, Mode},
+    function::FuncArgs,
+    signal::UserSignalSender,
+    PyResult, VirtualMachine,
+};
+use serde::{Deserialize, Deserializer};
+
+use super::{
+    ploy_module::PLOY_MODULE,
+    script_paths::{self, script_paths},
+};
+
+pub const SANDBOX_POLICY_PATH: &str = "data/sandbox.yaml";
+
+/// Exception raised in scripts that break the sandbox policy.
+pub const SANDBOX_VIOLATION: &str = "SandboxViolation";
+
+/// Modules none of the attributes of which lead to `os`, `sys` or `builtins`.
+const DEFAULT_ALLOWED_MODULES: [&str; 10] = [
+    "bisect",
+    "cmath",
+    "copy",
+    "decimal",
+    "functools",
+    "hashlib",
+    "heapq",
+    "itertools",
+    "math",
+    "operator",
+];
+
+/// Only allowed with `filesystem`, even when listed in the allowed modules.
+const FILESYSTEM_MODULES: [&str; 15] = [
+    "_io",
+    "fileinput",
+    "glob",
+    "io",
+    "mmap",
+    "nt",
+    "os",
+    "pathlib",
+    "posix",
+    "shutil",
+    "sqlite3",
+    "subprocess",
+    "tarfile",
+    "tempfile",
+    "zipfile",
+];
+
+/// Only allowed with `network`, even when listed in the allowed modules.
+const NETWORK_MODULES: [&str; 12] = [
+    "_socket",
+    "_ssl",
+    "asyncio",
+    "ftplib",
+    "http",
+    "poplib",
+    "select",
+    "selectors",
+    "smtplib",
+    "socket",
+    "ssl",
+    "urllib",
+];
+
+/// Builtins that run code the imports of which were not checked when the script was loaded.
+const DYNAMIC_CODE_BUILTINS: [&str; 3] = ["compile", "eval", "exec"];
+
+/// Attributes leading from any object to the classes, functions and modules the engine loaded,
+/// e.g. `os` through `().__class__.__base__.__subclasses__()`, or to other frames. Scripts may
+/// not use them, `attrgetter` and `methodcaller` would look them up from a string.
+const RESTRICTED_ATTRIBUTES: [&str; 22] = [
+    "__base__",
+    "__bases__",
+    "__builtins__",
+    "__class__",
+    "__closure__",
+    "__code__",
+    "__dict__",
+    "__func__",
+    "__getattribute__",
+    "__globals__",
+    "__mro__",
+    "__self__",
+    "__subclasses__",
+    "attrgetter",
+    "cr_frame",
+    "f_back",
+    "f_builtins",
+    "f_globals",
+    "f_locals",
+    "gi_frame",
+    "methodcaller",
+    "tb_frame",
+];
+
+/// Builtins looking up an attribute by name, refused for restricted attributes at runtime.
+const ATTRIBUTE_BUILTINS: [&str; 4] = ["delattr", "getattr", "hasattr", "setattr"];
+
+/// What scripts may use. Imports and restricted attributes are checked when a script is loaded,
+/// imports again when it runs. `open`, dynamic code, `vars` of an object and the lookup of
+/// restricted attributes by name are refused at runtime.
+///
+/// Only the modules that lead to no others through their attributes are allowed by default.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SandboxPolicy {
+    /// Top level modules scripts may import, besides `ploy` and the modules of the scripts
+    /// directory
+    pub allowed_modules: Vec<String>,
+    pub filesystem: bool,
+    pub network: bool,
+    /// Bytes a run may allocate on top of what it frees, or the size of the linear memory of a
+    /// WebAssembly run, e.g. `64MB`
+    #[serde(deserialize_with = "deserialize_memory")]
+    pub max_memory: Option<usize>,
+    /// Function calls a run may make. RustPython has no hook to count single instructions,
+    /// loops that call nothing are only bounded by the timeout.
+    pub max_calls: Option<u64>,
+    /// Fuel a WebAssembly run may consume, about one unit per instruction
+    pub max_fuel: Option<u64>,
+}
+
+impl Default for SandboxPolicy {
+    fn default() -> Self {
+        SandboxPolicy {
+            allowed_modules: DEFAULT_ALLOWED_MODULES
+                .iter()
+                .map(|module| module.to_string())
+                .collect(),
+            filesystem: false,
+            network: false,
+            max_memory: None,
+            max_calls: None,
+            max_fuel: None,
+        }
+    }
+}
+
+/// Changes a script node makes to the global policy.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SandboxOverrides {
+    /// Added to the allowed modules of the global policy
+    pub allowed_modules: Vec<String>,
+    pub filesystem: Option<bool>,
+    pub network: Option<bool>,
+    pub max_memory: Option<usize>,
+    pub max_calls: Option<u64>,
+    pub max_fuel: Option<u64>,
+}
+
+impl SandboxPolicy {
+    /// Loads the global policy, the default one applies if the file does not exist.
+    pub fn load(path: &str) -> Result<Self> {
+        if !Path::new(path).exists() {
+            return Ok(SandboxPolicy::default());
+        }
+
+        let policy: SandboxPolicy = serde_yaml::from_str(&std::fs::read_to_string(path)?)?;
+
+        info!("Sandbox policy loaded: {}", path);
+
+        Ok(policy)
+    }
+
+    pub fn with_overrides(&self, overrides: &SandboxOverrides) -> SandboxPolicy {
+        let mut policy = self.clone();
+
+        policy
+            .allowed_modules
+            .extend(overrides.allowed_modules.iter().cloned());
+        policy.filesystem = overrides.filesystem.unwrap_or(self.filesystem);
+        policy.network = overrides.network.unwrap_or(self.network);
+        policy.max_memory = overrides.max_memory.or(self.max_memory);
+        policy.max_calls = overrides.max_calls.or(self.max_calls);
+        policy.max_fuel = overrides.max_fuel.or(self.max_fuel);
+
+        policy
+    }
+
+    pub fn is_module_allowed(&self, module: &str) -> bool {
+        let top_level = module.split('.').next().unwrap_or(module);
+
+        if FILESYSTEM_MODULES.contains(&top_level) {
+            return self.filesystem;
+        }
+
+        if NETWORK_MODULES.contains(&top_level) {
+            return self.network;
+        }
+
+        top_level == PLOY_MODULE
+            || self
+                .allowed_modules
+                .iter()
+                .any(|allowed| allowed == top_level)
+            || is_script_module(top_level)
+    }
+
+    /// Checks the modules imported by a script, as found by `imported_modules`.
+    pub fn check_imports(&self, modules: &[String]) -> Result<(), String> {
+        match modules
+            .iter()
+            .find(|module| !self.is_module_allowed(module))
+        {
+            Some(module) => Err(import_violation(module)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parses a memory size such as `512KB`, `64MB` or `1GB`, in bytes when there is no unit.
+pub fn parse_memory(memory: &str) -> Result<usize, String> {
+    let memory = memory.trim();
+    let split = memory
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(memory.len());
+
+    let (amount, unit) = memory.split_at(split);
+    let amount = amount
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid memory size '{memory}'"))?;
+
+    let multiplier: usize = match unit.trim() {
+        "" | "B" => 1,
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        _ => {
+            return Err(format!(
+                "Invalid memory size '{memory}', use B, KB, MB or GB"
+            ))
+        }
+    };
+
+    match amount.checked_mul(multiplier) {
+        Some(0) => Err("Memory size should be greater than zero".to_string()),
+        Some(bytes) => Ok(bytes),
+        None => Err(format!("Memory size '{memory}' is too large")),
+    }
+}
+
+pub fn deserialize_memory<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<usize>, D::Error> {
+    let memory = String::deserialize(deserializer)?;
+
+    parse_memory(&memory)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Modules imported anywhere in the code, including nested functions and classes.
+pub fn imported_modules(code: &CodeObject) -> Vec<String> {
+    let mut modules = Vec::default();
+    let mut arg_state = OpArgState::default();
+
+    for unit in code.instructions.iter() {
+        let (instruction, arg) = arg_state.get(*unit);
+
+        if let Instruction::ImportName { idx } = instruction {
+            let module = &code.names[idx.get(arg) as usize];
+
+            if !module.is_empty() {
+                modules.push(module.clone());
+            }
+        }
+    }
+
+    for constant in code.constants.iter() {
+        if let BorrowedConstant::Code { code } = constant.borrow_constant() {
+            modules.extend(imported_modules(code));
+        }
+    }
+
+    modules
+}
+
+/// Checks that the code, including nested functions and classes, uses no restricted attribute.
+pub fn check_attributes(code: &CodeObject) -> Result<(), String> {
+    match restricted_attribute(code) {
+        Some(attribute) => Err(attribute_violation(&attribute)),
+        None => Ok(()),
+    }
+}
+
+fn restricted_attribute(code: &CodeObject) -> Option<String> {
+    code.names
+        .iter()
+        .find(|name| RESTRICTED_ATTRIBUTES.contains(&name.as_str()))
+        .cloned()
+        .or_else(|| {
+            code.constants
+                .iter()
+                .find_map(|constant| match constant.borrow_constant() {
+                    BorrowedConstant::Code { code } => restricted_attribute(code),
+                    _ => None,
+                })
+        })
+}
+
+fn attribute_violation(attribute: &str) -> String {
+    format!("Attribute '{attribute}' is not allowed by the sandbox policy")
+}
+
+/// File of a module of the scripts directory or of the script root of the running script.
+fn script_module_file(module: &str) -> Option<PathBuf> {
+    script_paths::script_directories()
+        .into_iter()
+        .flat_map(|directory| {
+            [
+                directory.join(format!("{module}.py")),
+                directory.join(module).join("__init__.py"),
+            ]
+        })
+        .find(|path| path.is_file())
+}
+
+fn is_script_module(module: &str) -> bool {
+    script_module_file(module).is_some()
+}
+
+fn import_violation(module: &str) -> String {
+    format!("Import of module '{module}' is not allowed by the sandbox policy")
+}
+
+/// Modification time and imports of a module of the scripts directory.
+type CheckedModule = (Option<SystemTime>, Vec<String>);
+
+/// Counts the bytes allocated by threads that run a script with a memory budget. Memory budgets
+/// are only enforced when the binary installs it as its global allocator.
+pub struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<Option<&'static AtomicIsize>> = const { Cell::new(None) };
+    static MEMORY_USAGE: &'static AtomicIsize = Box::leak(Box::new(AtomicIsize::new(0)));
+    static ACTIVE_SANDBOX: RefCell<Option<ActiveSandbox>> = const { RefCell::new(None) };
+    static CHECKED_MODULES: RefCell<HashMap<PathBuf, CheckedModule>> =
+        RefCell::new(HashMap::default());
+}
+
+impl CountingAllocator {
+    fn count(bytes: isize) {
+        let _ = ALLOCATED.try_with(|allocated| {
+            if let Some(allocated) = allocated.get() {
+                allocated.fetch_add(bytes, Ordering::Relaxed);
+            }
+        });
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::count(layout.size() as isize);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::count(layout.size() as isize);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        Self::count(-(layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::count(new_size as isize - layout.size() as isize);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Bytes allocated by the current run of the calling thread, to be watched from another thread.
+pub fn memory_usage() -> &'static AtomicIsize {
+    MEMORY_USAGE.with(|usage| *usage)
+}
+
+struct ActiveSandbox {
+    policy: SandboxPolicy,
+    calls: u64,
+    interrupt: Option<UserSignalSender>,
+}
+
+/// Replaces the builtins that scripts must not use freely with guarded ones, and adds the
+/// `SandboxViolation` exception. The guards only apply while a sandbox is active.
+pub fn install(vm: &VirtualMachine) -> PyResult<()> {
+    let violation_type = vm.ctx.new_exception_type(
+        "builtins",
+        SANDBOX_VIOLATION,
+        Some(vec![vm.ctx.exceptions.exception_type.to_owned()]),
+    );
+    vm.builtins
+        .set_attr(SANDBOX_VIOLATION, violation_type, vm)?;
+
+    let import = vm.builtins.get_attr("__import__", vm)?;
+    let guarded_import =
+        vm.new_function("__import__", move |args: FuncArgs, vm: &VirtualMachine| {
+            guard_import(&args, vm)?;
+            import.call(args, vm)
+        });
+    vm.builtins.set_attr("__import__", guarded_import, vm)?;
+
+    let open = vm.builtins.get_attr("open", vm)?;
+    let guarded_open = vm.new_function("open", move |args: FuncArgs, vm: &VirtualMachine| {
+        if is_restricted(vm, |policy| !policy.filesystem) {
+            return Err(violation(
+                vm,
+                "Filesystem access is not allowed by the sandbox policy".to_string(),
+            ));
+        }
+
+        open.call(args, vm)
+    });
+    vm.builtins.set_attr("open", guarded_open, vm)?;
+
+    for name in DYNAMIC_CODE_BUILTINS {
+        let builtin = vm.builtins.get_attr(name, vm)?;
+        let guarded = vm.new_function(name, move |args: FuncArgs, vm: &VirtualMachine| {
+            if is_restricted(vm, |_| true) {
+                return Err(violation(
+                    vm,
+                    format!("{name}() is not allowed by the sandbox policy"),
+                ));
+            }
+
+            builtin.call(args, vm)
+        });
+        vm.builtins.set_attr(name, guarded, vm)?;
+    }
+
+    for name in ATTRIBUTE_BUILTINS {
+        let builtin = vm.builtins.get_attr(name, vm)?;
+        let guarded = vm.new_function(name, move |args: FuncArgs, vm: &VirtualMachine| {
+            let attribute = args
+                .args
+                .get(1)
+                .and_then(|attribute| attribute.downcast_ref::<PyStr>())
+                .map(|attribute| attribute.as_str().to_string())
+                .filter(|attribute| RESTRICTED_ATTRIBUTES.contains(&attribute.as_str()));
+
+            if let Some(attribute) = attribute {
+                if is_restricted(vm, |_| true) {
+                    return Err(violation(vm, attribute_violation(&attribute)));
+                }
+            }
+
+            builtin.call(args, vm)
+        });
+        vm.builtins.set_attr(name, guarded, vm)?;
+    }
+
+    // The dict of an object holds what its restricted attributes would return
+    let vars = vm.builtins.get_attr("vars", vm)?;
+    let guarded_vars = vm.new_function("vars", move |args: FuncArgs, vm: &VirtualMachine| {
+        if !args.args.is_empty() && is_restricted(vm, |_| true) {
+            return Err(violation(
+                vm,
+                "vars() of an object is not allowed by the sandbox policy".to_string(),
+            ));
+        }
+
+        vars.call(args, vm)
+    });
+    vm.builtins.set_attr("vars", guarded_vars, vm)?;
+
+    Ok(())
+}
+
+/// Runs `f` with the policy applied to the code of scripts. Budgets are only enforced with an
+/// interrupt sender, memory is watched by the caller through `memory_usage`.
+pub fn run_sandboxed<R>(
+    vm: &VirtualMachine,
+    policy: &SandboxPolicy,
+    interrupt: Option<UserSignalSender>,
+    f: impl FnOnce() -> R,
+) -> R {
+    let memory = memory_usage();
+    memory.store(0, Ordering::SeqCst);
+
+    if policy.max_memory.is_some() {
+        ALLOCATED.with(|allocated| allocated.set(Some(memory)));
+    }
+
+    let count_calls = policy.max_calls.is_some() && interrupt.is_some();
+
+    ACTIVE_SANDBOX.with(|active| {
+        *active.borrow_mut() = Some(ActiveSandbox {
+            policy: policy.clone(),
+            calls: 0,
+            interrupt,
+        })
+    });
+
+    if count_calls {
+        *vm.profile_func.borrow_mut() = vm.new_function("count_call", count_call).into();
+        vm.use_tracing.set(true);
+    }
+
+    let result = f();
+
+    if count_calls {
+        *vm.profile_func.borrow_mut() = vm.ctx.none();
+        vm.use_tracing.set(!vm.is_none(&vm.trace_func.borrow()));
+    }
+
+    ACTIVE_SANDBOX.with(|active| *active.borrow_mut() = None);
+    ALLOCATED.with(|allocated| allocated.set(None));
+
+    result
+}
+
+/// Raises a `SandboxViolation` if the active run is over one of its budgets. Sent as interrupt,
+/// so the exception is raised in the script even if it runs no call.
+pub fn check_budgets(vm: &VirtualMachine) -> PyResult<()> {
+    let exceeded = ACTIVE_SANDBOX.with(|active| {
+        let active = active.borrow();
+        let active = active.as_ref()?;
+
+        if let Some(max_memory) = active.policy.max_memory {
+            if memory_usage().load(Ordering::SeqCst) > max_memory as isize {
+                return Some(format!(
+                    "Script exceeded its memory budget of {max_memory} bytes"
+                ));
+            }
+        }
+
+        match active.policy.max_calls {
+            Some(max_calls) if active.calls > max_calls => {
+                Some(format!("Script exceeded its budget of {max_calls} calls"))
+            }
+            _ => None,
+        }
+    });
+
+    match exceeded {
+        Some(message) => Err(violation(vm, message)),
+        None => Ok(()),
+    }
+}
+
+fn count_call(args: FuncArgs, _vm: &VirtualMachine) {
+    let is_call = args
+        .args
+        .get(1)
+        .and_then(|event| event.downcast_ref::<PyStr>())
+        .is_some_and(|event| event.as_str() == "call");
+
+    if !is_call {
+        return;
+    }
+
+    let interrupt = ACTIVE_SANDBOX.with(|active| {
+        let mut active = active.borrow_mut();
+        let active = active.as_mut()?;
+        active.calls += 1;
+
+        match active.policy.max_calls {
+            Some(max_calls) if active.calls > max_calls => active.interrupt.clone(),
+            _ => None,
+        }
+    });
+
+    // Errors of profile functions are ignored, the exception is raised through an interrupt.
+    // It is sent again on every call in case another interpreter cleared the signal first.
+    if let Some(interrupt) = interrupt {
+        let _ = interrupt.send(Box::new(check_budgets));
+    }
+}
+
+pub fn violation(vm: &VirtualMachine, message: String) -> PyBaseExceptionRef {
+    let violation_type = vm
+        .builtins
+        .get_attr(SANDBOX_VIOLATION, vm)
+        .ok()
+        .and_then(|violation_type| violation_type.downcast::<PyType>().ok())
+        .unwrap_or_else(|| vm.ctx.exceptions.exception_type.to_owned());
+
+    vm.new_exception_msg(violation_type, message)
+}
+
+/// Whether a sandbox is active, the calling code belongs to a script and `restricted` holds
+/// for the policy.
+fn is_restricted(vm: &VirtualMachine, restricted: impl FnOnce(&SandboxPolicy) -> bool) -> bool {
+    let applies = ACTIVE_SANDBOX.with(|active| {
+        active
+            .borrow()
+            .as_ref()
+            .map(|active| restricted(&active.policy))
+    });
+
+    applies == Some(true) && is_script_frame(vm)
+}
+
+/// Code of scripts comes from the scripts directory or is inline in a process definition. The
+/// file the code was compiled from is used, as scripts can rebind `__file__`.
+fn is_script_frame(vm: &VirtualMachine) -> bool {
+    let Some(frame) = vm.current_frame() else {
+        return false;
+    };
+
+    let file = Path::new(frame.code.source_path.as_str());
+
+    file.extension()
+        .is_some_and(|extension| extension == "ploy")
+        || file.starts_with(&script_paths().scripts)
+}
+
+fn guard_import(args: &FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+    let Some(policy) =
+        ACTIVE_SANDBOX.with(|active| active.borrow().as_ref().map(|active| active.policy.clone()))
+    else {
+        return Ok(());
+    };
+
+    let level = args
+        .args
+        .get(4)
+        .or_else(|| args.kwargs.get("level"))
+        .and_then(|level| level.try_to_value::<usize>(vm).ok())
+        .unwrap_or(0);
+
+    let module = args
+        .args
+        .first()
+        .and_then(|module| module.downcast_ref::<PyStr>())
+        .map(|module| module.as_str().to_string())
+        .unwrap_or_default();
+
+    if level > 0 || module.is_empty() || !is_script_frame(vm) {
+        return Ok(());
+    }
+
+    if !policy.is_module_allowed(&module) {
+        return Err(violation(vm, import_violation(&module)));
+    }
+
+    let top_level = module.split('.').next().unwrap_or(&module);
+
+    if is_script_module(top_level) {
+        check_script_module(vm, &policy, top_level)?;
+    }
+
+    Ok(())
+}
+
+/// Imports of modules that are already loaded do not go through `__import__`, so the imports of
+/// modules of the scripts directory are checked before they are loaded.
+fn check_script_module(vm: &VirtualMachine, policy: &SandboxPolicy, module: &str) -> PyResult<()> {
+    let path = script_module_file(module).ok_or_else(|| {
+        vm.new_import_error(
+            format!("No module named '{module}'"),
+            vm.ctx.new_str(module),
+        )
+    })?;
+
+    let modified = std::fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+
+    let cached = CHECKED_MODULES.with(|checked| {
+        checked
+            .borrow()
+            .get(&path)
+            .filter(|(checked_modified, _)| *checked_modified == modified)
+            .map(|(_, imports)| imports.clone())
+    });
+
+    let imports = match cached {
+        Some(imports) => imports,
+        None => {
+            let source =
+                std::fs::read_to_string(&path).map_err(|e| vm.new_os_error(e.to_string()))?;
+            let code = compiler::compile(
+                &source,
+                Mode::Exec,
+                path.to_string_lossy().to_string(),
+                CompileOpts::default(),
+            )
+            .map_err(|e| vm.new_syntax_error(&e, Some(&source)))?;
+
+            check_attributes(&code)
+                .map_err(|message| violation(vm, format!("{}: {}", path.display(), message)))?;
+
+            let imports = imported_modules(&code);
+
+            CHECKED_MODULES.with(|checked| {
+                checked
+                    .borrow_mut()
+                    .insert(path.clone(), (modified, imports.clone()))
+            });
+
+            imports
+        }
+    };
+
+    policy
+        .check_imports(&imports)
+        .map_err(|message| violation(vm, format!("{}: {}", path.display(), message)))
+}
@@ -1,27 +1,325 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
 use rustpython::{
     self,
-    vm::{self, stdlib, Settings},
+    vm::{
+        bytecode::{
+            BorrowedConstant, CodeObject, Constant, Instruction, MakeFunctionFlags, OpArg,
+            OpArgState,
+        },
+        compiler::{self, CompileError, CompileOpts, Mode},
+        signal::{self, UserSignalSender},
+        stdlib, Interpreter, Settings,
+    },
 };
-use serde_json::{value::Serializer, Map, Value};
-use vm::py_serde::{deserialize, serialize};
 
 use crate::definition::step::{Step, StepInputRequest};
 
+use super::{
+    ploy_module,
+    sandbox::{self, SandboxOverrides, SandboxPolicy},
+    script_engine::ScriptRuntime,
+    script_paths::script_paths,
+};
+
+/// Used when a script node does not set a `timeout`.
+pub const DEFAULT_SCRIPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn create_interpreter() -> Interpreter {
+    build_interpreter(None)
+}
+
+/// Interpreter that runs the closures sent through the returned sender between two
+/// instructions, e.g. to raise an exception in a script that runs for too long.
+pub fn create_interruptible_interpreter() -> (Interpreter, UserSignalSender) {
+    let (sender, receiver) = signal::user_signal_channel();
+
+    (build_interpreter(Some(receiver)), sender)
+}
+
+fn build_interpreter(signal_receiver: Option<signal::UserSignalReceiver>) -> Interpreter {
+    let paths = script_paths();
+    let mut settings: Settings =
+        (Settings::default()).with_path(paths.python_lib.to_string_lossy().to_string());
+    for packages in paths.site_packages.iter() {
+        settings = settings.with_path(packages.to_string_lossy().to_string());
+    }
+    settings.no_sig_int = true;
+    settings.debug = true;
+    settings.inspect = true;
+
+    let interpreter = Interpreter::with_init(settings, |vm| {
+        vm.add_native_modules(stdlib::get_module_inits());
+        vm.add_native_modules(rustpython_stdlib::get_module_inits());
+
+        if let Some(signal_receiver) = signal_receiver {
+            vm.set_user_signal_channel(signal_receiver);
+        }
+    });
+
+    interpreter.enter(|vm| {
+        sandbox::install(vm).expect("Sandbox installs");
+        ploy_module::install(vm).expect("Ploy module installs");
+        vm.insert_sys_path(vm.new_pyobj(paths.scripts.to_string_lossy().as_ref()))
+            .expect("add path");
+        vm.import("pre-import", None, 0).expect("Pre-import works");
+    });
+
+    interpreter
+}
+
+/// Checks that the script module compiles, only imports what the sandbox policy allows and
+/// defines an `execute(input)` function. The module is not run.
+pub fn check_script(module: &ScriptModule, policy: &SandboxPolicy) -> Result<(), String> {
+    let script = &module.name;
+    let path = module
+        .file(&script_paths().scripts, "py")
+        .to_string_lossy()
+        .to_string();
+
+    let source = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Script module '{script}' can not be read from {path}: {e}"))?;
+
+    let code = compiler::compile(&source, Mode::Exec, path.clone(), CompileOpts::default())
+        .map_err(|err| format_compile_error(&path, &err))?;
+
+    policy
+        .check_imports(&sandbox::imported_modules(&code))
+        .and_then(|_| sandbox::check_attributes(&code))
+        .and_then(|_| check_execute_function(&code))
+        .map_err(|message| format!("{path}: {message}"))
+}
+
+/// Checks that the inline script only imports what the sandbox policy allows and defines an
+/// `execute(input)` function. The script is not run.
+pub fn check_inline_script(script: &InlineScript, policy: &SandboxPolicy) -> Result<(), String> {
+    policy
+        .check_imports(&sandbox::imported_modules(&script.code))
+        .and_then(|_| sandbox::check_attributes(&script.code))
+        .and_then(|_| check_execute_function(&script.code))
+        .map_err(|message| format!("{}: script {}: {message}", script.path, script.name))
+}
+
+/// Checks that the top level of the code binds `execute`. A function it defines there has to
+/// accept one positional argument, ones bound by decorators, imports or calls are only checked
+/// when the script runs.
+fn check_execute_function(code: &CodeObject) -> Result<(), String> {
+    use Instruction::{Duplicate, LoadConst, MakeFunction, Rotate2, StoreAttr};
+
+    let mut arg_state = OpArgState::default();
+    let instructions = code
+        .instructions
+        .iter()
+        .map(|unit| arg_state.get(*unit))
+        .filter(|(instruction, _)| !matches!(instruction, Instruction::ExtendedArg))
+        .collect::<Vec<(Instruction, OpArg)>>();
+
+    let store = instructions
+        .iter()
+        .rposition(|(instruction, arg)| match instruction {
+            Instruction::StoreLocal(idx) | Instruction::StoreGlobal(idx) => {
+                code.names[idx.get(*arg) as usize].as_str() == "execute"
+            }
+            _ => false,
+        })
+        .ok_or_else(|| "does not define an 'execute' function".to_string())?;
+
+    let mut bound = &instructions[..store];
+
+    // A `def` sets the docstring of the function before binding it, a lambda binds it directly
+    if let [function @ .., (Duplicate, _), _, (Rotate2, _), (StoreAttr { .. }, _)] = bound {
+        bound = function;
+    }
+
+    let (function, flags) = match bound {
+        [.., (LoadConst { idx }, arg), (LoadConst { .. }, _), (MakeFunction(flags), flags_arg)] => {
+            (
+                &code.constants[idx.get(*arg) as usize],
+                flags.get(*flags_arg),
+            )
+        }
+        [.., (LoadConst { .. }, _)] => return Err("'execute' is not callable".to_string()),
+        _ => return Ok(()),
+    };
+
+    let BorrowedConstant::Code { code: function } = function.borrow_constant() else {
+        return Ok(());
+    };
+
+    // The number of defaults is not known without running the code
+    if function.arg_count == 0
+        || (function.arg_count > 1 && !flags.contains(MakeFunctionFlags::DEFAULTS))
+    {
+        return Err("'execute' should accept exactly one positional argument (input)".to_string());
+    }
+
+    Ok(())
+}
+
+fn format_compile_error(path: &str, err: &CompileError) -> String {
+    match err.location {
+        Some(location) => format!("{path}:{}:{}: {}", location.row, location.column, err.error),
+        None => format!("{path}: {}", err.error),
+    }
+}
+
+/// Script body embedded in a process definition, compiled when the definition is loaded.
+#[derive(Debug, Clone)]
+pub struct InlineScript {
+    /// Module name the script runs as, e.g. `Main.script1`
+    pub name: String,
+    /// Process definition file, for line numbers in errors and tracebacks
+    pub path: String,
+    /// Script root of the definition, helper modules are imported from it first
+    pub root: Option<String>,
+    pub code: Arc<CodeObject>,
+    /// Hash of the body, tells versions of the script apart
+    pub source_hash: u64,
+}
+
+impl InlineScript {
+    /// Compiles the body starting at `first_line` of the file, so that line numbers match the
+    /// process definition. The common indentation of the body is removed.
+    pub fn compile(
+        name: String,
+        path: String,
+        root: Option<String>,
+        body: &str,
+        first_line: usize,
+    ) -> Result<Self, String> {
+        let source = format!(
+            "{}{}",
+            "\n".repeat(first_line.saturating_sub(1)),
+            dedent(body)
+        );
+
+        let code = compiler::compile(&source, Mode::Exec, path.clone(), CompileOpts::default())
+            .map_err(|err| format_compile_error(&path, &err))?;
+
+        let mut hasher = DefaultHasher::new();
+        body.hash(&mut hasher);
+
+        Ok(InlineScript {
+            name,
+            path,
+            root,
+            code: Arc::new(code),
+            source_hash: hasher.finish(),
+        })
+    }
+}
+
+impl PartialEq for InlineScript {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && Arc::ptr_eq(&self.code, &other.code)
+    }
+}
+
+fn dedent(body: &str) -> String {
+    let indentation = body
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    body.lines()
+        .map(|line| line.get(indentation..).unwrap_or(""))
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Module of a scripts directory, looked up in the script root of its definition first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptModule {
+    pub name: String,
+    pub root: Option<String>,
+}
+
+impl ScriptModule {
+    pub fn new(name: String, root: Option<String>) -> Self {
+        ScriptModule { name, root }
+    }
+
+    /// File of the module in `directory`, or in the script root within it when it is there.
+    pub fn file(&self, directory: &Path, extension: &str) -> PathBuf {
+        let file_name = format!("{}.{extension}", self.name);
+
+        self.root
+            .as_ref()
+            .map(|root| directory.join(root).join(&file_name))
+            .filter(|file| file.is_file())
+            .unwrap_or_else(|| directory.join(file_name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptSource {
+    /// Module of the scripts directory
+    Module(ScriptModule),
+    Inline(InlineScript),
+    /// Module of the WebAssembly scripts directory
+    Wasm(ScriptModule),
+}
+
+impl ScriptSource {
+    pub fn name(&self) -> &str {
+        match self {
+            ScriptSource::Module(module) | ScriptSource::Wasm(module) => &module.name,
+            ScriptSource::Inline(script) => &script.name,
+        }
+    }
+
+    /// Script root of the definition of the script.
+    pub fn root(&self) -> Option<&str> {
+        match self {
+            ScriptSource::Module(module) | ScriptSource::Wasm(module) => module.root.as_deref(),
+            ScriptSource::Inline(script) => script.root.as_deref(),
+        }
+    }
+
+    pub fn runtime(&self) -> ScriptRuntime {
+        match self {
+            ScriptSource::Module(_) | ScriptSource::Inline(_) => ScriptRuntime::Python,
+            ScriptSource::Wasm(_) => ScriptRuntime::Wasm,
+        }
+    }
+}
+
+impl fmt::Display for ScriptSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ScriptStep {
     id: String,
-    script: String,
+    script: ScriptSource,
     input_schema: String,
     output_schema: String,
+    timeout: Duration,
+    sandbox: SandboxOverrides,
+    /// Outputs only depend on the inputs, so they are cached across process instances
+    pure: bool,
     inputs: Vec<StepInputRequest>,
 }
 
 impl ScriptStep {
     pub fn new(
         id: String,
-        script: String,
+        script: ScriptSource,
         input_schema: String,
         output_schema: String,
+        timeout: Duration,
+        sandbox: SandboxOverrides,
         inputs: Vec<StepInputRequest>,
     ) -> Self {
         Self {
@@ -30,8 +328,16 @@ impl ScriptStep {
             inputs,
             input_schema,
             output_schema,
+            timeout,
+            sandbox,
+            pure: false,
         }
     }
+
+    pub fn with_pure(mut self, pure: bool) -> Self {
+        self.pure = pure;
+        self
+    }
 }
 
 impl Step for ScriptStep {
@@ -51,78 +357,30 @@ impl Step for ScriptStep {
         Some(self.output_schema.clone())
     }
 
+    fn script(&self) -> Option<ScriptSource> {
+        Some(self.script.clone())
+    }
+
+    fn sandbox(&self) -> Option<SandboxOverrides> {
+        Some(self.sandbox.clone())
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Some(self.timeout)
+    }
+
     fn start(
         &self,
         ctx: &dyn crate::definition::step::ManageStep,
     ) -> anyhow::Result<crate::definition::step::StepResult> {
-        // TODO! - Set python library path from environment variable + bundle w docker image
-        let mut settings: Settings =
-            (Settings::default()).with_path("./Lib".to_string());
-        settings.no_sig_int = true;
-        settings.debug = true;
-        settings.inspect = true;
-
-        let inputs = Value::Object(ctx.get_inputs().clone());
-
-        let interpreter = rustpython::vm::Interpreter::with_init(settings, |vm| {
-            vm.add_native_modules(stdlib::get_module_inits());
-            vm.add_native_modules(rustpython_stdlib::get_module_inits());
-        });
-
-        interpreter.enter(|vm| {
-            vm.insert_sys_path(vm.new_pyobj("data/python"))
-                .expect("add path");
-            vm.import("pre-import", None, 0).expect("Pre-import works");
-        });
-
-        let script_result = interpreter
-            .enter(|vm| -> vm::PyResult<Value> {
-                let module_name = vm.ctx.new_str(self.script.clone());
-
-                let module_res = vm.import(&module_name, None, 0);
+        let job_id = ctx.run_script(
+            self.script.clone(),
+            self.timeout,
+            self.sandbox.clone(),
+            self.pure,
+        );
 
-                if let Err(err) = module_res {
-                    vm.print_exception(err);
-
-                    return Ok(Value::Null);
-                }
-
-                let module = module_res.expect("Module imported");
-
-                let execute_fn = module
-                    .get_attr("execute", vm)
-                    .expect("Should have execute function");
-
-                let py_input = deserialize(vm, inputs).expect("Convert JSON to PyObject");
-
-                let execution_result = execute_fn.call((py_input,), vm);
-
-                if let Err(err) = execution_result {
-                    vm.print_exception(err);
-
-                    return Ok(Value::Null);
-                }
-
-                let result = execution_result.expect("Execution result");
-
-                let result_json =
-                    serialize(vm, &result, Serializer).expect("Convert PyObject to JSON");
-
-                Ok(result_json)
-            })
-            .expect("All is okay");
-
-        if let Value::Object(result) = script_result {
-            return Ok(crate::definition::step::StepResult::Completed(result));
-        } else {
-            // return Ok(crate::definition::step::StepResult::Failed(
-            //     "Script did not return a JSON object".to_string(),
-            // ));
-
-            Ok(crate::definition::step::StepResult::Completed(
-                Map::default(),
-            ))
-        }
+        Ok(crate::definition::step::StepResult::AsyncJob(job_id))
     }
 
     fn get_type(&self) -> crate::definition::step::StepType {
@@ -0,0 +1,231 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    env,
+    hash::{Hash, Hasher},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+use serde_json::{Map, Value};
+
+use super::{sandbox, script::ScriptSource, script_paths::script_paths};
+
+/// Maximum number of cached outputs.
+pub const CACHE_ENTRIES_VAR: &str = "PLOY_SCRIPT_CACHE_ENTRIES";
+/// Maximum size of all cached inputs and outputs, e.g. `64MB`.
+pub const CACHE_SIZE_VAR: &str = "PLOY_SCRIPT_CACHE_SIZE";
+/// Outputs larger than this are not cached, e.g. `1MB`.
+pub const CACHE_ENTRY_SIZE_VAR: &str = "PLOY_SCRIPT_CACHE_ENTRY_SIZE";
+
+const DEFAULT_ENTRIES: usize = 10_000;
+const DEFAULT_SIZE: usize = 64 << 20;
+const DEFAULT_ENTRY_SIZE: usize = 1 << 20;
+
+/// Identifies the outputs of a script version for the same inputs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ScriptCacheKey {
+    script: String,
+    version: String,
+    inputs_hash: u64,
+}
+
+struct CacheEntry {
+    /// Serialized inputs, compared on lookup in case two inputs have the same hash
+    inputs: String,
+    outputs: Map<String, Value>,
+    size: usize,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<ScriptCacheKey, CacheEntry>,
+    /// Keys by the tick they were last used at, least recently used first
+    recently_used: BTreeMap<u64, ScriptCacheKey>,
+    tick: u64,
+    size: usize,
+    stats: ScriptCacheStats,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScriptCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub inserts: u64,
+    pub evictions: u64,
+    /// Outputs not cached, because they are larger than the entry size limit or no entries are
+    /// allowed
+    pub skipped: u64,
+    pub entries: usize,
+    pub size: usize,
+}
+
+/// Outputs of `pure` script nodes, shared by all process instances. Least recently used outputs
+/// are evicted once the entry or size limit is reached.
+pub struct ScriptCache {
+    max_entries: usize,
+    max_size: usize,
+    max_entry_size: usize,
+    state: Mutex<CacheState>,
+}
+
+impl Default for ScriptCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_ENTRIES, DEFAULT_SIZE, DEFAULT_ENTRY_SIZE)
+    }
+}
+
+impl ScriptCache {
+    pub fn new(max_entries: usize, max_size: usize, max_entry_size: usize) -> Self {
+        Self {
+            max_entries,
+            max_size,
+            max_entry_size: max_entry_size.min(max_size),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Limits set by the environment or the defaults.
+    pub fn from_env() -> Result<Self> {
+        let size = |var: &str, default: usize| match env::var(var) {
+            Ok(size) => sandbox::parse_memory(&size).map_err(|e| anyhow!("{var}: {e}")),
+            Err(_) => Ok(default),
+        };
+
+        let max_entries = match env::var(CACHE_ENTRIES_VAR) {
+            Ok(entries) => entries.trim().parse().map_err(|e| {
+                anyhow!("{CACHE_ENTRIES_VAR}: Invalid entry count '{entries}': {e}")
+            })?,
+            Err(_) => DEFAULT_ENTRIES,
+        };
+
+        let cache = Self::new(
+            max_entries,
+            size(CACHE_SIZE_VAR, DEFAULT_SIZE)?,
+            size(CACHE_ENTRY_SIZE_VAR, DEFAULT_ENTRY_SIZE)?,
+        );
+
+        info!(
+            "Script cache: {} entries, {} bytes, {} bytes per entry",
+            cache.max_entries, cache.max_size, cache.max_entry_size
+        );
+
+        Ok(cache)
+    }
+
+    /// Key of the inputs for the current version of the script, none when the version can not
+    /// be told, e.g. because the script file is missing.
+    pub fn key(
+        script: &ScriptSource,
+        inputs: &Map<String, Value>,
+    ) -> Option<(ScriptCacheKey, String)> {
+        let version = script_version(script)?;
+        let inputs = Value::Object(inputs.clone()).to_string();
+
+        let mut hasher = DefaultHasher::new();
+        inputs.hash(&mut hasher);
+
+        let key = ScriptCacheKey {
+            script: script.name().to_string(),
+            version,
+            inputs_hash: hasher.finish(),
+        };
+
+        Some((key, inputs))
+    }
+
+    pub fn get(&self, key: &ScriptCacheKey, inputs: &str) -> Option<Map<String, Value>> {
+        let mut state = self.state.lock().expect("Script cache lock is poisoned");
+        let state = &mut *state;
+
+        state.tick += 1;
+        let tick = state.tick;
+
+        match state.entries.get_mut(key) {
+            Some(entry) if entry.inputs == inputs => {
+                state.recently_used.remove(&entry.last_used);
+                state.recently_used.insert(tick, key.clone());
+                entry.last_used = tick;
+                state.stats.hits += 1;
+
+                debug!("Script cache hit: {}", key.script);
+
+                Some(entry.outputs.clone())
+            }
+            _ => {
+                state.stats.misses += 1;
+
+                None
+            }
+        }
+    }
+
+    pub fn insert(&self, key: ScriptCacheKey, inputs: String, outputs: Map<String, Value>) {
+        let size = inputs.len() + Value::Object(outputs.clone()).to_string().len();
+
+        let mut state = self.state.lock().expect("Script cache lock is poisoned");
+
+        if size > self.max_entry_size || self.max_entries == 0 {
+            state.stats.skipped += 1;
+            return;
+        }
+
+        state.tick += 1;
+        let tick = state.tick;
+
+        if let Some(previous) = state.entries.remove(&key) {
+            state.recently_used.remove(&previous.last_used);
+            state.size -= previous.size;
+        }
+
+        while state.entries.len() >= self.max_entries || state.size + size > self.max_size {
+            let Some((_, evicted)) = state.recently_used.pop_first() else {
+                break;
+            };
+
+            if let Some(evicted) = state.entries.remove(&evicted) {
+                state.size -= evicted.size;
+                state.stats.evictions += 1;
+            }
+        }
+
+        state.recently_used.insert(tick, key.clone());
+        state.entries.insert(
+            key,
+            CacheEntry {
+                inputs,
+                outputs,
+                size,
+                last_used: tick,
+            },
+        );
+        state.size += size;
+        state.stats.inserts += 1;
+    }
+
+    pub fn stats(&self) -> ScriptCacheStats {
+        let state = self.state.lock().expect("Script cache lock is poisoned");
+
+        ScriptCacheStats {
+            entries: state.entries.len(),
+            size: state.size,
+            ..state.stats
+        }
+    }
+}
+
+/// Modification time of the script file, or the hash of an inline script body.
+fn script_version(script: &ScriptSource) -> Option<String> {
+    let file = match script {
+        ScriptSource::Module(module) => module.file(&script_paths().scripts, "py"),
+        ScriptSource::Wasm(module) => module.file(&script_paths().wasm_scripts, "wasm"),
+        ScriptSource::Inline(inline) => return Some(format!("{:x}", inline.source_hash)),
+    };
+
+    let modified = std::fs::metadata(&file).ok()?.modified().ok()?;
+    let modified = modified.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+
+    Some(format!("{}@{modified}", file.display()))
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::definition::step::StepError;
+
+use super::{
+    interpreter_pool,
+    ploy_module::ScriptContext,
+    sandbox::SandboxPolicy,
+    script::{self, ScriptSource},
+    wasm_engine::WasmEngine,
+};
+
+pub const SCRIPT_NOT_FOUND: &str = "ScriptNotFound";
+
+/// The script did not return a JSON object as its outputs.
+pub const CONTRACT_VIOLATION: &str = "ContractViolation";
+
+/// Runtime of a script node, set with its `runtime` attribute.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScriptRuntime {
+    #[default]
+    Python,
+    /// WASI module exporting `execute`, see `wasm_engine`
+    Wasm,
+}
+
+/// Runs the scripts of one runtime.
+pub trait ScriptEngine {
+    /// Checks that the script can be loaded and exposes `execute`, when a definition is loaded.
+    fn check(&mut self, script: &ScriptSource, policy: &SandboxPolicy) -> Result<(), String>;
+
+    /// Calls `execute` of the script with the step inputs and returns its outputs.
+    fn execute(
+        &mut self,
+        script: &ScriptSource,
+        input: Value,
+        timeout: Option<Duration>,
+        policy: &SandboxPolicy,
+        context: &ScriptContext,
+    ) -> Result<Map<String, Value>, StepError>;
+}
+
+/// Runs Python scripts with RustPython, on the interpreter pool of the current thread.
+#[derive(Default)]
+pub struct PythonEngine;
+
+impl ScriptEngine for PythonEngine {
+    fn check(&mut self, script: &ScriptSource, policy: &SandboxPolicy) -> Result<(), String> {
+        match script {
+            ScriptSource::Module(module) => script::check_script(module, policy),
+            ScriptSource::Inline(inline_script) => {
+                script::check_inline_script(inline_script, policy)
+            }
+            ScriptSource::Wasm(module) => {
+                Err(format!("Script '{}' is not a Python script", module.name))
+            }
+        }
+    }
+
+    fn execute(
+        &mut self,
+        script: &ScriptSource,
+        input: Value,
+        timeout: Option<Duration>,
+        policy: &SandboxPolicy,
+        context: &ScriptContext,
+    ) -> Result<Map<String, Value>, StepError> {
+        interpreter_pool::execute_script(script, input, timeout, policy, context)
+    }
+}
+
+/// One engine per runtime, scripts run on the engine of their runtime.
+#[derive(Default)]
+pub struct ScriptEngines {
+    python: PythonEngine,
+    wasm: WasmEngine,
+}
+
+impl ScriptEngines {
+    pub fn get(&mut self, runtime: ScriptRuntime) -> &mut dyn ScriptEngine {
+        match runtime {
+            ScriptRuntime::Python => &mut self.python,
+            ScriptRuntime::Wasm => &mut self.wasm,
+        }
+    }
+}
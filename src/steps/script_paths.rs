@@ -0,0 +1,193 @@
+use std::{
+    cell::RefCell,
+    env,
+    path::{Component, Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use rustpython::vm::{PyResult, VirtualMachine};
+
+pub const PYTHON_LIB_VAR: &str = "PLOY_PYTHON_LIB";
+pub const SCRIPTS_VAR: &str = "PLOY_SCRIPTS_PATH";
+/// Paths separated like `PATH`, e.g. `vendor:packages.zip`
+pub const SITE_PACKAGES_VAR: &str = "PLOY_SITE_PACKAGES";
+pub const WASM_SCRIPTS_VAR: &str = "PLOY_WASM_SCRIPTS_PATH";
+
+const DEFAULT_PYTHON_LIB: &str = "Lib";
+const DEFAULT_SCRIPTS: &str = "data/python";
+const DEFAULT_WASM_SCRIPTS: &str = "data/wasm";
+
+/// Where the Python standard library, third-party packages and scripts are loaded from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptPaths {
+    pub python_lib: PathBuf,
+    pub scripts: PathBuf,
+    /// Directories and zip archives of pure-Python packages, searched after the standard library
+    pub site_packages: Vec<PathBuf>,
+    pub wasm_scripts: PathBuf,
+}
+
+static SCRIPT_PATHS: OnceLock<ScriptPaths> = OnceLock::new();
+
+thread_local! {
+    /// Script root of the definition whose script runs on this thread
+    static SCRIPT_ROOT: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+impl ScriptPaths {
+    /// Paths set by the environment or the defaults, relative paths are resolved against the
+    /// working directory.
+    pub fn from_env() -> Self {
+        let path = |var: &str, default: &str| {
+            absolute(env::var_os(var).map_or_else(|| PathBuf::from(default), PathBuf::from))
+        };
+
+        let site_packages = env::var_os(SITE_PACKAGES_VAR)
+            .map(|paths| {
+                env::split_paths(&paths)
+                    .filter(|path| !path.as_os_str().is_empty())
+                    .map(absolute)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        ScriptPaths {
+            python_lib: path(PYTHON_LIB_VAR, DEFAULT_PYTHON_LIB),
+            scripts: path(SCRIPTS_VAR, DEFAULT_SCRIPTS),
+            site_packages,
+            wasm_scripts: path(WASM_SCRIPTS_VAR, DEFAULT_WASM_SCRIPTS),
+        }
+    }
+
+    /// Checks that the paths exist, reporting all missing ones at once.
+    pub fn check(&self) -> Result<()> {
+        let mut missing = Vec::default();
+
+        if !self.python_lib.is_dir() {
+            missing.push(format!(
+                "Python standard library {} (set {PYTHON_LIB_VAR})",
+                self.python_lib.display()
+            ));
+        }
+
+        if !self.scripts.is_dir() {
+            missing.push(format!(
+                "Scripts directory {} (set {SCRIPTS_VAR})",
+                self.scripts.display()
+            ));
+        }
+
+        for packages in self.site_packages.iter() {
+            if !packages.is_dir() && !is_zip_archive(packages) {
+                missing.push(format!(
+                    "Site packages {} is not a directory or zip archive (set {SITE_PACKAGES_VAR})",
+                    packages.display()
+                ));
+            }
+        }
+
+        if !self.wasm_scripts.is_dir() {
+            warn!(
+                "WebAssembly scripts directory {} does not exist, wasm script nodes will not load",
+                self.wasm_scripts.display()
+            );
+        }
+
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "Script paths are missing:\n  {}",
+                missing.join("\n  ")
+            ));
+        }
+
+        info!(
+            "Script paths: library {}, scripts {}, site packages [{}]",
+            self.python_lib.display(),
+            self.scripts.display(),
+            self.site_packages
+                .iter()
+                .map(|packages| packages.display().to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+
+        Ok(())
+    }
+
+    /// Makes these the paths of all interpreters and engines, before the first one is created.
+    pub fn install(self) {
+        if SCRIPT_PATHS.set(self).is_err() {
+            warn!("Script paths are already in use and can not be changed");
+        }
+    }
+}
+
+/// Installed paths, the ones of the environment if none were installed.
+pub fn script_paths() -> &'static ScriptPaths {
+    SCRIPT_PATHS.get_or_init(ScriptPaths::from_env)
+}
+
+fn absolute(path: PathBuf) -> PathBuf {
+    std::fs::canonicalize(&path).unwrap_or_else(|_| match env::current_dir() {
+        Ok(directory) => directory.join(path),
+        Err(_) => path,
+    })
+}
+
+fn is_zip_archive(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|extension| extension == "zip")
+}
+
+/// Checks the script root of a definition, a relative path within the scripts directories.
+pub fn check_script_root(root: &str) -> Result<(), String> {
+    let path = Path::new(root);
+
+    if root.is_empty()
+        || !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(format!(
+            "Script root '{root}' should be a relative path within the scripts directory"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Directories scripts import their modules from, the root of their definition first.
+pub fn script_directories() -> Vec<PathBuf> {
+    let scripts = &script_paths().scripts;
+
+    SCRIPT_ROOT.with(|root| match root.borrow().as_ref() {
+        Some(root) => vec![scripts.join(root), scripts.clone()],
+        None => vec![scripts.clone()],
+    })
+}
+
+/// Runs `f` with the script root of a definition first on `sys.path`.
+pub fn run_in_root<R>(
+    vm: &VirtualMachine,
+    root: Option<&str>,
+    f: impl FnOnce() -> PyResult<R>,
+) -> PyResult<R> {
+    let Some(root) = root else {
+        return f();
+    };
+
+    let directory = script_paths().scripts.join(root);
+    let directory = vm.ctx.new_str(directory.to_string_lossy().as_ref());
+
+    vm.insert_sys_path(directory.clone().into())?;
+    SCRIPT_ROOT.with(|current| *current.borrow_mut() = Some(PathBuf::from(root)));
+
+    let result = f();
+
+    SCRIPT_ROOT.with(|current| *current.borrow_mut() = None);
+    let sys_path = vm.sys_module.get_attr("path", vm)?;
+    vm.call_method(&sys_path, "remove", (directory,))?;
+
+    result
+}
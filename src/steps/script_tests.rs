@@ -0,0 +1,330 @@
+use std::{collections::HashMap, path::Path, sync::Arc, time::Instant};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::definition::{
+    parser, process_definition::ProcessDefinition, schema_registry::SchemaRegistry, step::StepError,
+};
+
+use super::{
+    ploy_module::ScriptContext, sandbox::SandboxPolicy, script::DEFAULT_SCRIPT_TIMEOUT,
+    script_engine::ScriptEngines,
+};
+
+pub const SCRIPT_TESTS_PATH: &str = "data/script_tests";
+
+/// The fixture inputs do not match the input schema of the node.
+pub const INPUT_SCHEMA_VIOLATION: &str = "InputSchemaViolation";
+
+/// The script outputs do not match the output schema of the node.
+pub const OUTPUT_SCHEMA_VIOLATION: &str = "OutputSchemaViolation";
+
+/// Test cases of one script node, loaded from a `.yaml` file.
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+pub struct ScriptTestSuite {
+    /// Process definition the node belongs to
+    pub process: String,
+    pub node: String,
+    pub cases: Vec<ScriptTestCase>,
+}
+
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptTestCase {
+    pub name: String,
+    pub input: Map<String, Value>,
+    /// Outputs the script should return, outputs not listed are not compared
+    pub expected: Option<Map<String, Value>>,
+    /// Error type the case should fail with instead
+    pub expected_error: Option<String>,
+    /// Process variables exposed to the script through the `ploy` module
+    #[serde(default)]
+    pub variables: Map<String, Value>,
+}
+
+#[derive(Serialize, PartialEq, Debug, Clone, Copy)]
+pub enum ScriptTestStatus {
+    Passed,
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptTestResult {
+    pub suite: String,
+    pub process: String,
+    pub node: String,
+    pub case: String,
+    pub status: ScriptTestStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<Map<String, Value>>,
+    pub duration_ms: u128,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ScriptTestReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub results: Vec<ScriptTestResult>,
+}
+
+impl ScriptTestReport {
+    fn add(&mut self, result: ScriptTestResult) {
+        match result.status {
+            ScriptTestStatus::Passed => self.passed += 1,
+            ScriptTestStatus::Failed => self.failed += 1,
+        }
+
+        self.results.push(result);
+    }
+}
+
+/// Why a case failed, with the outputs if the script returned.
+struct CaseFailure {
+    message: String,
+    outputs: Option<Map<String, Value>>,
+}
+
+impl CaseFailure {
+    fn new(message: String) -> Self {
+        Self {
+            message,
+            outputs: None,
+        }
+    }
+}
+
+/// Runs script nodes against fixtures, on the same engines, sandbox policy and schemas as
+/// processes do, without starting one.
+pub struct ScriptTestRunner {
+    schema_registry: Arc<SchemaRegistry>,
+    sandbox_policy: Arc<SandboxPolicy>,
+    definitions_directory: String,
+    definitions: HashMap<String, Arc<ProcessDefinition>>,
+    engines: ScriptEngines,
+}
+
+impl ScriptTestRunner {
+    pub fn new(
+        schema_registry: Arc<SchemaRegistry>,
+        sandbox_policy: Arc<SandboxPolicy>,
+        definitions_directory: &str,
+    ) -> Self {
+        Self {
+            schema_registry,
+            sandbox_policy,
+            definitions_directory: definitions_directory.to_string(),
+            definitions: HashMap::default(),
+            engines: ScriptEngines::default(),
+        }
+    }
+
+    /// Runs every `.yaml` suite of the directory, in file name order.
+    pub fn run_directory(&mut self, directory: &str) -> Result<ScriptTestReport> {
+        let mut paths = std::fs::read_dir(directory)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.sort();
+
+        let mut report = ScriptTestReport::default();
+
+        for path in paths {
+            if path.extension().and_then(|e| e.to_str()) != Some("yaml") {
+                continue;
+            }
+
+            let suite_name = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or_default()
+                .to_string();
+
+            let suite = Self::load_suite(&path)?;
+
+            for result in self.run_suite(&suite_name, &suite) {
+                report.add(result);
+            }
+        }
+
+        info!(
+            "Script tests: {} passed, {} failed",
+            report.passed, report.failed
+        );
+
+        Ok(report)
+    }
+
+    fn load_suite(path: &Path) -> Result<ScriptTestSuite> {
+        let file_contents = std::fs::read_to_string(path)?;
+
+        serde_yaml::from_str(&file_contents)
+            .map_err(|e| anyhow!("Invalid script test suite {}: {}", path.display(), e))
+    }
+
+    pub fn run_suite(
+        &mut self,
+        suite_name: &str,
+        suite: &ScriptTestSuite,
+    ) -> Vec<ScriptTestResult> {
+        suite
+            .cases
+            .iter()
+            .map(|case| {
+                let started = Instant::now();
+                let result = self.run_case(suite, case);
+
+                let (status, message, outputs) = match result {
+                    Ok(outputs) => (ScriptTestStatus::Passed, None, outputs),
+                    Err(failure) => {
+                        warn!(
+                            "Script test {}/{} failed: {}",
+                            suite_name, case.name, failure.message
+                        );
+
+                        (
+                            ScriptTestStatus::Failed,
+                            Some(failure.message),
+                            failure.outputs,
+                        )
+                    }
+                };
+
+                ScriptTestResult {
+                    suite: suite_name.to_string(),
+                    process: suite.process.clone(),
+                    node: suite.node.clone(),
+                    case: case.name.clone(),
+                    status,
+                    message,
+                    outputs,
+                    duration_ms: started.elapsed().as_millis(),
+                }
+            })
+            .collect()
+    }
+
+    /// Outputs of a passed case, none when it expected an error.
+    fn run_case(
+        &mut self,
+        suite: &ScriptTestSuite,
+        case: &ScriptTestCase,
+    ) -> Result<Option<Map<String, Value>>, CaseFailure> {
+        let definition = self
+            .definition(&suite.process)
+            .map_err(|e| CaseFailure::new(e.to_string()))?;
+
+        let step = definition.get_step(&suite.node).ok_or_else(|| {
+            CaseFailure::new(format!(
+                "Process {} has no node {}",
+                suite.process, suite.node
+            ))
+        })?;
+
+        let script = step
+            .script()
+            .ok_or_else(|| CaseFailure::new(format!("Node {} is not a script node", suite.node)))?;
+
+        let policy = self
+            .sandbox_policy
+            .with_overrides(&step.sandbox().unwrap_or_default());
+
+        let context = ScriptContext {
+            process_id: format!("test-{}", case.name),
+            step_id: suite.node.clone(),
+            definition_name: definition.get_name().to_string(),
+            definition_version: definition.get_version().to_string(),
+            variables: case.variables.clone(),
+        };
+
+        let input_schema = step.input_schema();
+        let output_schema = step.output_schema();
+        let timeout = step.timeout().unwrap_or(DEFAULT_SCRIPT_TIMEOUT);
+
+        let result = self
+            .validate(&case.input, input_schema.as_deref(), INPUT_SCHEMA_VIOLATION)
+            .and_then(|_| {
+                self.engines.get(script.runtime()).execute(
+                    &script,
+                    Value::Object(case.input.clone()),
+                    Some(timeout),
+                    &policy,
+                    &context,
+                )
+            })
+            .and_then(|outputs| {
+                self.validate(&outputs, output_schema.as_deref(), OUTPUT_SCHEMA_VIOLATION)
+                    .map(|_| outputs)
+            });
+
+        Self::check_result(case, result)
+    }
+
+    fn check_result(
+        case: &ScriptTestCase,
+        result: Result<Map<String, Value>, StepError>,
+    ) -> Result<Option<Map<String, Value>>, CaseFailure> {
+        match (result, &case.expected_error) {
+            (Ok(outputs), Some(expected_error)) => Err(CaseFailure {
+                message: format!("Expected {expected_error}, but the script succeeded"),
+                outputs: Some(outputs),
+            }),
+            (Ok(outputs), None) => {
+                let mismatches = case
+                    .expected
+                    .iter()
+                    .flatten()
+                    .filter(|(name, value)| outputs.get(name.as_str()) != Some(value))
+                    .map(|(name, value)| match outputs.get(name.as_str()) {
+                        Some(actual) => format!("{name}: expected {value}, got {actual}"),
+                        None => format!("{name}: expected {value}, got no output"),
+                    })
+                    .collect::<Vec<String>>();
+
+                if mismatches.is_empty() {
+                    Ok(Some(outputs))
+                } else {
+                    Err(CaseFailure {
+                        message: mismatches.join("\n"),
+                        outputs: Some(outputs),
+                    })
+                }
+            }
+            (Err(error), Some(expected_error)) if &error.error_type == expected_error => Ok(None),
+            (Err(error), _) => Err(CaseFailure::new(error.to_string())),
+        }
+    }
+
+    fn validate(
+        &self,
+        map: &Map<String, Value>,
+        schema_name: Option<&str>,
+        error_type: &str,
+    ) -> Result<(), StepError> {
+        let Some(schema_name) = schema_name else {
+            return Ok(());
+        };
+
+        self.schema_registry
+            .validate(&Value::Object(map.clone()), schema_name)
+            .map_err(|e| StepError::new(error_type, format!("{schema_name}: {e}")))
+    }
+
+    fn definition(&mut self, process_name: &str) -> Result<Arc<ProcessDefinition>> {
+        if !self.definitions.contains_key(process_name) {
+            let file_name = format!("{}/{process_name}.ploy", self.definitions_directory);
+            let file_contents = std::fs::read_to_string(&file_name)
+                .map_err(|e| anyhow!("Process definition {file_name} can not be read: {e}"))?;
+
+            let definition = parser::parse_xml(process_name, &file_contents)?;
+            self.definitions
+                .insert(process_name.to_string(), Arc::new(definition));
+        }
+
+        Ok(self.definitions[process_name].clone())
+    }
+}
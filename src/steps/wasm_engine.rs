@@ -0,0 +1,510 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::Level;
+use serde_json::{Map, Value};
+use wasmi::{
+    core::{Trap, TrapCode, ValueType},
+    errors::MemoryError,
+    Caller, Config, Engine, Error, Extern, ExternType, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, TypedFunc, Value as WasmValue,
+};
+
+use crate::definition::step::StepError;
+
+use super::{
+    ploy_module::{self, ScriptContext},
+    sandbox::{SandboxPolicy, SANDBOX_VIOLATION},
+    script::{ScriptModule, ScriptSource},
+    script_engine::{ScriptEngine, CONTRACT_VIOLATION, SCRIPT_NOT_FOUND},
+    script_paths::script_paths,
+};
+
+/// Fuel of a run when the sandbox policy sets no `maxFuel`. Runs can not be interrupted when
+/// they time out, so they always have a fuel budget.
+pub const DEFAULT_FUEL: u64 = 1_000_000_000;
+
+/// Size of the linear memory when the sandbox policy sets no `maxMemory`.
+pub const DEFAULT_MEMORY: usize = 256 << 20;
+
+/// The module trapped, e.g. on `unreachable`, an out of bounds access or a call to `proc_exit`.
+const WASM_TRAP: &str = "WasmTrap";
+
+/// The module can not be instantiated or does not export what the engine calls.
+const INVALID_MODULE: &str = "InvalidModule";
+
+const WASI_MODULE: &str = "wasi_snapshot_preview1";
+
+/// WASI functions with an implementation, the other ones fail with `EBADF` for file
+/// descriptors and paths and with `ENOSYS` otherwise.
+const WASI_FUNCTIONS: [&str; 9] = [
+    "args_get",
+    "args_sizes_get",
+    "clock_time_get",
+    "environ_get",
+    "environ_sizes_get",
+    "fd_write",
+    "proc_exit",
+    "random_get",
+    "sched_yield",
+];
+
+const ERRNO_SUCCESS: i32 = 0;
+const ERRNO_BADF: i32 = 8;
+const ERRNO_NOSYS: i32 = 52;
+
+/// Bytes of standard output and error kept for the log, per stream and run.
+const MAX_OUTPUT: usize = 64 << 10;
+
+/// Seed of `random_get`, the same for every run so that runs are reproducible.
+const RANDOM_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// Host state of a run.
+struct RunState {
+    limits: StoreLimits,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    random: u64,
+}
+
+struct CompiledModule {
+    modified: Option<SystemTime>,
+    module: Module,
+    linker: Linker<RunState>,
+}
+
+/// Runs the modules of the WebAssembly scripts directory with wasmi.
+///
+/// A module exports its `memory`, `alloc(len: i32) -> i32` for the engine to write the input
+/// JSON to, and `execute(ptr: i32, len: i32) -> i64`, which returns where the output JSON object
+/// is as `ptr << 32 | len`. WASI reactors are initialized with `_initialize` first.
+///
+/// Runs are deterministic: clocks are frozen at the epoch and random data is the same for every
+/// run. Standard output and error go to the engine log, files, sockets and the environment are
+/// not available.
+pub struct WasmEngine {
+    engine: Engine,
+    modules: HashMap<String, CompiledModule>,
+}
+
+impl Default for WasmEngine {
+    fn default() -> Self {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+
+        WasmEngine {
+            engine: Engine::new(&config),
+            modules: HashMap::default(),
+        }
+    }
+}
+
+impl WasmEngine {
+    /// Compiles the module, unless it is cached and the file did not change since. Modules are
+    /// cached by file, as definitions may have their own module of the same name.
+    fn load(&mut self, module: &ScriptModule) -> Result<String, StepError> {
+        let path = module
+            .file(&script_paths().wasm_scripts, "wasm")
+            .to_string_lossy()
+            .to_string();
+        let modified = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        if self
+            .modules
+            .get(&path)
+            .is_some_and(|compiled| compiled.modified == modified)
+        {
+            return Ok(path);
+        }
+
+        let bytes = std::fs::read(&path).map_err(|e| {
+            StepError::new(
+                SCRIPT_NOT_FOUND,
+                format!(
+                    "Script module '{}' can not be read from {path}: {e}",
+                    module.name
+                ),
+            )
+        })?;
+
+        let invalid = |e: Error| StepError::new(INVALID_MODULE, format!("{path}: {e}"));
+        let compiled = Module::new(&self.engine, bytes.as_slice()).map_err(invalid)?;
+        let linker = wasi_linker(&self.engine, &compiled).map_err(invalid)?;
+
+        self.modules.insert(
+            path.clone(),
+            CompiledModule {
+                modified,
+                module: compiled,
+                linker,
+            },
+        );
+
+        Ok(path)
+    }
+
+    fn module(script: &ScriptSource) -> Result<&ScriptModule, StepError> {
+        match script {
+            ScriptSource::Wasm(module) => Ok(module),
+            _ => Err(StepError::new(
+                SCRIPT_NOT_FOUND,
+                format!("Script '{script}' is not a WebAssembly module"),
+            )),
+        }
+    }
+}
+
+impl ScriptEngine for WasmEngine {
+    fn check(&mut self, script: &ScriptSource, policy: &SandboxPolicy) -> Result<(), String> {
+        let module = Self::module(script).map_err(|e| e.message)?;
+        let path = self.load(module).map_err(|e| e.message)?;
+
+        let run = Run::new(&self.engine, &self.modules[&path], policy)
+            .map_err(|e| format!("{path}: {}", e.message))?;
+        run.log_output(None);
+
+        Ok(())
+    }
+
+    fn execute(
+        &mut self,
+        script: &ScriptSource,
+        input: Value,
+        timeout: Option<Duration>,
+        policy: &SandboxPolicy,
+        context: &ScriptContext,
+    ) -> Result<Map<String, Value>, StepError> {
+        let started = Instant::now();
+        let path = self.load(Self::module(script)?)?;
+
+        let mut run = Run::new(&self.engine, &self.modules[&path], policy)?;
+        let input = serde_json::to_vec(&input).expect("JSON values serialize");
+        let result = run.call(&input);
+        run.log_output(Some(context));
+
+        let output = result.map_err(|trap| trap_error(trap, policy))?;
+
+        if let Some(timeout) = timeout.filter(|timeout| started.elapsed() > *timeout) {
+            return Err(StepError::new(
+                "TimeoutError",
+                format!("Script exceeded its timeout of {} ms", timeout.as_millis()),
+            ));
+        }
+
+        match serde_json::from_slice::<Value>(&output) {
+            Ok(Value::Object(outputs)) => Ok(outputs),
+            Ok(_) => Err(StepError::new(
+                CONTRACT_VIOLATION,
+                format!("Script '{script}' returned JSON that is not an object"),
+            )),
+            Err(e) => Err(StepError::new(
+                CONTRACT_VIOLATION,
+                format!("Script '{script}' returned invalid JSON: {e}"),
+            )),
+        }
+    }
+}
+
+/// Instance of a module for a single run, with the fuel and memory budgets of the policy.
+struct Run {
+    store: Store<RunState>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    execute: TypedFunc<(i32, i32), i64>,
+}
+
+impl Run {
+    fn new(
+        engine: &Engine,
+        compiled: &CompiledModule,
+        policy: &SandboxPolicy,
+    ) -> Result<Self, StepError> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(policy.max_memory.unwrap_or(DEFAULT_MEMORY))
+            .trap_on_grow_failure(true)
+            .build();
+
+        let mut store = Store::new(
+            engine,
+            RunState {
+                limits,
+                stdout: Vec::default(),
+                stderr: Vec::default(),
+                random: RANDOM_SEED,
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store
+            .add_fuel(policy.max_fuel.unwrap_or(DEFAULT_FUEL))
+            .expect("Fuel metering is enabled");
+
+        let invalid = |message: String| StepError::new(INVALID_MODULE, message);
+
+        let instance = compiled
+            .linker
+            .instantiate(&mut store, &compiled.module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(|e| match e {
+                Error::Trap(trap) => trap_error(trap, policy),
+                Error::Memory(MemoryError::OutOfBoundsGrowth) => memory_budget_error(policy),
+                e => invalid(e.to_string()),
+            })?;
+
+        if let Ok(initialize) = instance.get_typed_func::<(), ()>(&store, "_initialize") {
+            initialize
+                .call(&mut store, ())
+                .map_err(|trap| trap_error(trap, policy))?;
+        }
+
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| invalid("module does not export its memory".to_string()))?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&store, "alloc")
+            .map_err(|e| invalid(format!("module should export alloc(len: i32) -> i32: {e}")))?;
+        let execute = instance
+            .get_typed_func::<(i32, i32), i64>(&store, "execute")
+            .map_err(|e| {
+                invalid(format!(
+                    "module should export execute(ptr: i32, len: i32) -> i64: {e}"
+                ))
+            })?;
+
+        Ok(Run {
+            store,
+            memory,
+            alloc,
+            execute,
+        })
+    }
+
+    /// Writes the input JSON to the memory of the module, calls `execute` and reads the output.
+    fn call(&mut self, input: &[u8]) -> Result<Vec<u8>, Trap> {
+        let len = i32::try_from(input.len()).map_err(|_| Trap::new("Input is too large"))?;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+
+        self.memory
+            .write(&mut self.store, address(ptr), input)
+            .map_err(|_| out_of_bounds())?;
+
+        let output = self.execute.call(&mut self.store, (ptr, len))? as u64;
+        let (output_ptr, output_len) = ((output >> 32) as usize, (output & 0xffff_ffff) as usize);
+
+        // The length comes from the module, it is checked before the buffer is allocated
+        if output_ptr
+            .checked_add(output_len)
+            .filter(|&end| end <= self.memory.data(&self.store).len())
+            .is_none()
+        {
+            return Err(out_of_bounds());
+        }
+
+        let mut buffer = vec![0; output_len];
+
+        self.memory
+            .read(&self.store, output_ptr, &mut buffer)
+            .map_err(|_| out_of_bounds())?;
+
+        Ok(buffer)
+    }
+
+    fn log_output(&self, context: Option<&ScriptContext>) {
+        let state = self.store.data();
+
+        for (output, level) in [(&state.stdout, Level::Info), (&state.stderr, Level::Warn)] {
+            for line in String::from_utf8_lossy(output).lines() {
+                ploy_module::write_log(context, level, line);
+            }
+        }
+    }
+}
+
+fn trap_error(trap: Trap, policy: &SandboxPolicy) -> StepError {
+    if let Some(status) = trap.i32_exit_status() {
+        return StepError::new(
+            WASM_TRAP,
+            format!("Script exited with status {status} before returning its outputs"),
+        );
+    }
+
+    match trap.trap_code() {
+        Some(TrapCode::OutOfFuel) => StepError::new(
+            SANDBOX_VIOLATION,
+            format!(
+                "Script exceeded its fuel budget of {}",
+                policy.max_fuel.unwrap_or(DEFAULT_FUEL)
+            ),
+        ),
+        Some(TrapCode::GrowthOperationLimited) => memory_budget_error(policy),
+        _ => StepError::new(WASM_TRAP, trap.to_string()),
+    }
+}
+
+fn memory_budget_error(policy: &SandboxPolicy) -> StepError {
+    StepError::new(
+        SANDBOX_VIOLATION,
+        format!(
+            "Script exceeded its memory budget of {} bytes",
+            policy.max_memory.unwrap_or(DEFAULT_MEMORY)
+        ),
+    )
+}
+
+/// Linker with the WASI functions the module imports.
+fn wasi_linker(engine: &Engine, module: &Module) -> Result<Linker<RunState>, Error> {
+    let mut linker = Linker::new(engine);
+
+    linker
+        .func_wrap(WASI_MODULE, "args_get", |_: i32, _: i32| ERRNO_SUCCESS)?
+        .func_wrap(WASI_MODULE, "args_sizes_get", sizes_get)?
+        .func_wrap(WASI_MODULE, "clock_time_get", clock_time_get)?
+        .func_wrap(WASI_MODULE, "environ_get", |_: i32, _: i32| ERRNO_SUCCESS)?
+        .func_wrap(WASI_MODULE, "environ_sizes_get", sizes_get)?
+        .func_wrap(WASI_MODULE, "fd_write", fd_write)?
+        .func_wrap(
+            WASI_MODULE,
+            "proc_exit",
+            |status: i32| -> Result<(), Trap> { Err(Trap::i32_exit(status)) },
+        )?
+        .func_wrap(WASI_MODULE, "random_get", random_get)?
+        .func_wrap(WASI_MODULE, "sched_yield", || ERRNO_SUCCESS)?;
+
+    let mut unsupported = HashSet::new();
+
+    for import in module.imports() {
+        let (ExternType::Func(ty), WASI_MODULE) = (import.ty(), import.module()) else {
+            continue;
+        };
+
+        let name = import.name();
+
+        if WASI_FUNCTIONS.contains(&name) || !unsupported.insert(name) {
+            continue;
+        }
+
+        let errno = match name.starts_with("fd_") || name.starts_with("path_") {
+            true => ERRNO_BADF,
+            false => ERRNO_NOSYS,
+        };
+        let results = ty.results().to_vec();
+
+        linker.func_new(WASI_MODULE, name, ty.clone(), move |_, _, outputs| {
+            for (output, ty) in outputs.iter_mut().zip(&results) {
+                *output = match ty {
+                    ValueType::I32 => WasmValue::I32(errno),
+                    ty => WasmValue::default(*ty),
+                };
+            }
+
+            Ok(())
+        })?;
+    }
+
+    Ok(linker)
+}
+
+fn address(ptr: i32) -> usize {
+    ptr as u32 as usize
+}
+
+fn out_of_bounds() -> Trap {
+    Trap::from(TrapCode::MemoryOutOfBounds)
+}
+
+/// Memory of the module calling a host function, with the state of the run.
+fn memory<'a>(
+    caller: &'a mut Caller<'_, RunState>,
+) -> Result<(&'a mut [u8], &'a mut RunState), Trap> {
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| Trap::new("Module does not export its memory"))?;
+
+    Ok(memory.data_and_store_mut(caller))
+}
+
+fn slice(data: &mut [u8], ptr: usize, len: usize) -> Result<&mut [u8], Trap> {
+    data.get_mut(ptr..ptr.saturating_add(len))
+        .ok_or_else(out_of_bounds)
+}
+
+fn write(caller: &mut Caller<'_, RunState>, ptr: i32, bytes: &[u8]) -> Result<(), Trap> {
+    let (data, _) = memory(caller)?;
+    slice(data, address(ptr), bytes.len())?.copy_from_slice(bytes);
+
+    Ok(())
+}
+
+/// No arguments and no environment variables.
+fn sizes_get(mut caller: Caller<'_, RunState>, count: i32, size: i32) -> Result<i32, Trap> {
+    write(&mut caller, count, &0u32.to_le_bytes())?;
+    write(&mut caller, size, &0u32.to_le_bytes())?;
+
+    Ok(ERRNO_SUCCESS)
+}
+
+fn clock_time_get(
+    mut caller: Caller<'_, RunState>,
+    _id: i32,
+    _precision: i64,
+    time: i32,
+) -> Result<i32, Trap> {
+    write(&mut caller, time, &0u64.to_le_bytes())?;
+
+    Ok(ERRNO_SUCCESS)
+}
+
+/// Standard output and error are kept for the log, other descriptors do not exist.
+fn fd_write(
+    mut caller: Caller<'_, RunState>,
+    fd: i32,
+    iovs: i32,
+    iovs_len: i32,
+    written: i32,
+) -> Result<i32, Trap> {
+    if fd != 1 && fd != 2 {
+        return Ok(ERRNO_BADF);
+    }
+
+    let (data, state) = memory(&mut caller)?;
+    let output = match fd {
+        1 => &mut state.stdout,
+        _ => &mut state.stderr,
+    };
+    let mut total: u32 = 0;
+
+    for index in 0..address(iovs_len) {
+        let iov = slice(data, address(iovs).saturating_add(index * 8), 8)?;
+        let ptr = u32::from_le_bytes([iov[0], iov[1], iov[2], iov[3]]);
+        let len = u32::from_le_bytes([iov[4], iov[5], iov[6], iov[7]]);
+
+        let bytes = slice(data, ptr as usize, len as usize)?;
+        let kept = bytes.len().min(MAX_OUTPUT.saturating_sub(output.len()));
+        output.extend_from_slice(&bytes[..kept]);
+
+        total = total.wrapping_add(len);
+    }
+
+    write(&mut caller, written, &total.to_le_bytes())?;
+
+    Ok(ERRNO_SUCCESS)
+}
+
+/// Deterministic random data, xorshift64* seeded with the same value for every run.
+fn random_get(mut caller: Caller<'_, RunState>, buffer: i32, len: i32) -> Result<i32, Trap> {
+    let (data, state) = memory(&mut caller)?;
+
+    for byte in slice(data, address(buffer), address(len))? {
+        state.random ^= state.random >> 12;
+        state.random ^= state.random << 25;
+        state.random ^= state.random >> 27;
+
+        *byte = (state.random.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 56) as u8;
+    }
+
+    Ok(ERRNO_SUCCESS)
+}
@@ -0,0 +1,85 @@
+/// Byte range in the source of a type file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    pub fn to(&self, other: Span) -> Span {
+        Span::new(self.start, other.end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    String(String),
+    Number(serde_json::Number),
+    Boolean(bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotationArgument {
+    /// Positional arguments are named `value`
+    pub name: Ident,
+    pub value: Literal,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub name: Ident,
+    pub arguments: Vec<AnnotationArgument>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeRef {
+    pub name: Ident,
+    /// Number of `[]` suffixes
+    pub dimensions: usize,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: Ident,
+    pub optional: bool,
+    pub annotations: Vec<Annotation>,
+    pub rtype: TypeRef,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeclarationKind {
+    Identifier(TypeRef),
+    Type(Vec<Field>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    pub name: Ident,
+    pub annotations: Vec<Annotation>,
+    pub kind: DeclarationKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub namespace: Ident,
+    pub names: Vec<Ident>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Module {
+    pub imports: Vec<Import>,
+    pub declarations: Vec<Declaration>,
+}
@@ -0,0 +1,623 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Map, Value};
+
+use super::{
+    ast::{Annotation, Declaration, DeclarationKind, Literal, Module, Span, TypeRef},
+    parser, CompileError, Diagnostic, TYPE_FILE_EXTENSION,
+};
+
+const JSON_SCHEMA_DRAFT: &str = "http://json-schema.org/draft-04/schema#";
+
+/// Types available without an import, along with their JSON Schema `type`.
+const BUILT_IN_TYPES: &[(&str, Option<&str>)] = &[
+    ("string", Some("string")),
+    ("number", Some("number")),
+    ("integer", Some("integer")),
+    ("boolean", Some("boolean")),
+    ("object", Some("object")),
+    ("any", None),
+];
+
+fn built_in_type(name: &str) -> Option<Option<&'static str>> {
+    BUILT_IN_TYPES
+        .iter()
+        .find(|(built_in, _)| *built_in == name)
+        .map(|(_, schema_type)| *schema_type)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledType {
+    /// Namespace qualified name, e.g. `ch.swiss.test.FullName`
+    pub name: String,
+    pub schema: Value,
+}
+
+struct SourceFile {
+    path: PathBuf,
+    namespace: String,
+    source: String,
+    module: Module,
+    /// Type names visible in the file, mapped to their qualified names
+    scope: HashMap<String, String>,
+}
+
+impl SourceFile {
+    fn qualify(&self, name: &str) -> String {
+        qualify(&self.namespace, name)
+    }
+}
+
+fn qualify(namespace: &str, name: &str) -> String {
+    if namespace.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", namespace, name)
+    }
+}
+
+/// Compiles type files into JSON Schemas. Each file is a namespace named after its path
+/// relative to the types directory, so `ch/swiss/test.ptype` declares `ch.swiss.test` types.
+pub struct TypeCompiler {
+    files: Vec<SourceFile>,
+    // qualified name -> (file index, declaration index)
+    declarations: BTreeMap<String, (usize, usize)>,
+    errors: Vec<CompileError>,
+}
+
+impl TypeCompiler {
+    pub fn compile_directory(directory: &str) -> Result<Vec<CompiledType>, Vec<CompileError>> {
+        let root = Path::new(directory);
+
+        if !root.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut paths = Vec::default();
+        let mut errors = Vec::default();
+
+        if let Err(err) = Self::find_type_files(root, &mut paths) {
+            errors.push(CompileError::new(
+                root.to_path_buf(),
+                "",
+                Diagnostic::new(Span::default(), err.to_string()),
+            ));
+        }
+
+        paths.sort();
+
+        let mut sources = Vec::default();
+
+        for path in paths {
+            let namespace = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .with_extension("")
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<String>>()
+                .join(".");
+
+            match std::fs::read_to_string(&path) {
+                Ok(source) => sources.push((path, namespace, source)),
+                Err(err) => errors.push(CompileError::new(
+                    path,
+                    "",
+                    Diagnostic::new(Span::default(), err.to_string()),
+                )),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Self::compile_sources(sources)
+    }
+
+    fn find_type_files(directory: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                Self::find_type_files(&path, paths)?;
+            } else if path.extension().and_then(|e| e.to_str()) == Some(TYPE_FILE_EXTENSION) {
+                paths.push(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compiles `(path, namespace, source)` triples, returning either all compiled types or all
+    /// found errors.
+    pub fn compile_sources(
+        sources: Vec<(PathBuf, String, String)>,
+    ) -> Result<Vec<CompiledType>, Vec<CompileError>> {
+        let mut compiler = TypeCompiler {
+            files: Vec::default(),
+            declarations: BTreeMap::default(),
+            errors: Vec::default(),
+        };
+
+        for (path, namespace, source) in sources {
+            match parser::parse(&source) {
+                Ok(module) => compiler.files.push(SourceFile {
+                    path,
+                    namespace,
+                    source,
+                    module,
+                    scope: HashMap::default(),
+                }),
+                Err(diagnostic) => compiler
+                    .errors
+                    .push(CompileError::new(path, &source, diagnostic)),
+            }
+        }
+
+        compiler.collect_declarations();
+        compiler.resolve_imports();
+
+        let compiled_types = compiler.compile_declarations();
+
+        if compiler.errors.is_empty() {
+            Ok(compiled_types)
+        } else {
+            Err(compiler.errors)
+        }
+    }
+
+    fn report(&mut self, file_index: usize, diagnostics: Vec<Diagnostic>) {
+        let file = &self.files[file_index];
+
+        for diagnostic in diagnostics {
+            self.errors.push(CompileError::new(
+                file.path.clone(),
+                &file.source,
+                diagnostic,
+            ));
+        }
+    }
+
+    fn collect_declarations(&mut self) {
+        for file_index in 0..self.files.len() {
+            let mut diagnostics = Vec::default();
+            let file = &mut self.files[file_index];
+
+            for (declaration_index, declaration) in file.module.declarations.iter().enumerate() {
+                let name = &declaration.name;
+                let qualified_name = qualify(&file.namespace, &name.name);
+
+                if built_in_type(&name.name).is_some() {
+                    diagnostics.push(Diagnostic::new(
+                        name.span,
+                        format!("'{}' is a built-in type", name.name),
+                    ));
+                } else if self.declarations.contains_key(&qualified_name) {
+                    diagnostics.push(Diagnostic::new(
+                        name.span,
+                        format!("Type {} is declared more than once", qualified_name),
+                    ));
+                } else {
+                    self.declarations
+                        .insert(qualified_name.clone(), (file_index, declaration_index));
+                    file.scope.insert(name.name.clone(), qualified_name);
+                }
+            }
+
+            self.report(file_index, diagnostics);
+        }
+    }
+
+    fn resolve_imports(&mut self) {
+        let namespaces = self
+            .files
+            .iter()
+            .map(|file| file.namespace.clone())
+            .collect::<HashSet<String>>();
+
+        for file_index in 0..self.files.len() {
+            let mut diagnostics = Vec::default();
+            let file = &mut self.files[file_index];
+
+            for import in file.module.imports.iter() {
+                let namespace = &import.namespace;
+
+                if !namespaces.contains(&namespace.name) {
+                    diagnostics.push(Diagnostic::new(
+                        namespace.span,
+                        format!(
+                            "Namespace {} not found, expected a {}.{} file",
+                            namespace.name,
+                            namespace.name.replace('.', "/"),
+                            TYPE_FILE_EXTENSION
+                        ),
+                    ));
+                    continue;
+                }
+
+                for name in import.names.iter() {
+                    let qualified_name = qualify(&namespace.name, &name.name);
+
+                    if !self.declarations.contains_key(&qualified_name) {
+                        diagnostics.push(Diagnostic::new(
+                            name.span,
+                            format!(
+                                "Namespace {} does not declare {}",
+                                namespace.name, name.name
+                            ),
+                        ));
+                    } else if file.scope.contains_key(&name.name) {
+                        diagnostics.push(Diagnostic::new(
+                            name.span,
+                            format!("{} is already declared in this file", name.name),
+                        ));
+                    } else {
+                        file.scope.insert(name.name.clone(), qualified_name);
+                    }
+                }
+            }
+
+            self.report(file_index, diagnostics);
+        }
+    }
+
+    fn compile_declarations(&mut self) -> Vec<CompiledType> {
+        let mut compiled_types = Vec::default();
+
+        for file_index in 0..self.files.len() {
+            let mut diagnostics = Vec::default();
+            let file = &self.files[file_index];
+
+            for declaration in file.module.declarations.iter() {
+                let qualified_name = file.qualify(&declaration.name.name);
+
+                // Skipped declarations were already reported
+                if self
+                    .declarations
+                    .get(&qualified_name)
+                    .map(|(index, _)| *index)
+                    != Some(file_index)
+                {
+                    continue;
+                }
+
+                if let Some(schema) = self.compile_declaration(file, declaration, &mut diagnostics)
+                {
+                    compiled_types.push(CompiledType {
+                        name: qualified_name,
+                        schema,
+                    });
+                }
+            }
+
+            self.report(file_index, diagnostics);
+        }
+
+        compiled_types
+    }
+
+    fn compile_declaration(
+        &self,
+        file: &SourceFile,
+        declaration: &Declaration,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<Value> {
+        let mut schema = Map::default();
+        schema.insert("$schema".to_string(), json!(JSON_SCHEMA_DRAFT));
+        schema.insert("title".to_string(), json!(declaration.name.name));
+
+        match &declaration.kind {
+            DeclarationKind::Identifier(rtype) => {
+                // Reported on every identifier of a cycle, which may span multiple files
+                self.underlying_type(file, &declaration.name.name, &mut Vec::default())
+                    .map_err(|diagnostic| {
+                        diagnostics.push(Diagnostic::new(rtype.span, diagnostic.message))
+                    })
+                    .ok()?;
+
+                let type_schema = self.compile_type_ref(
+                    file,
+                    rtype,
+                    &declaration.annotations,
+                    true,
+                    diagnostics,
+                )?;
+
+                schema.extend(type_schema);
+            }
+            DeclarationKind::Type(fields) => {
+                let mut properties = Map::default();
+                let mut required = Vec::default();
+
+                for field in fields {
+                    if properties.contains_key(&field.name.name) {
+                        diagnostics.push(Diagnostic::new(
+                            field.name.span,
+                            format!("Field {} is declared more than once", field.name.name),
+                        ));
+                        continue;
+                    }
+
+                    let Some(field_schema) = self.compile_type_ref(
+                        file,
+                        &field.rtype,
+                        &field.annotations,
+                        false,
+                        diagnostics,
+                    ) else {
+                        continue;
+                    };
+
+                    if !field.optional {
+                        required.push(json!(field.name.name));
+                    }
+
+                    properties.insert(field.name.name.clone(), Value::Object(field_schema));
+                }
+
+                schema.insert("type".to_string(), json!("object"));
+
+                for annotation in declaration.annotations.iter() {
+                    if let Err(diagnostic) =
+                        Self::apply_annotation(annotation, Some("object"), &mut schema)
+                    {
+                        diagnostics.push(diagnostic);
+                    }
+                }
+
+                schema.insert("properties".to_string(), Value::Object(properties));
+
+                // Draft 4 does not allow an empty list of required properties
+                if !required.is_empty() {
+                    schema.insert("required".to_string(), Value::Array(required));
+                }
+            }
+        }
+
+        Some(Value::Object(schema))
+    }
+
+    /// JSON Schema `type` of a declared type, following identifiers to the type they refer to.
+    fn underlying_type(
+        &self,
+        file: &SourceFile,
+        name: &str,
+        stack: &mut Vec<String>,
+    ) -> Result<Option<&'static str>, Diagnostic> {
+        if let Some(schema_type) = built_in_type(name) {
+            return Ok(schema_type);
+        }
+
+        let qualified_name = file.scope.get(name).expect("Type is in scope");
+        let (file_index, declaration_index) = self.declarations[qualified_name];
+        let declaring_file = &self.files[file_index];
+        let declaration = &declaring_file.module.declarations[declaration_index];
+
+        let DeclarationKind::Identifier(rtype) = &declaration.kind else {
+            return Ok(Some("object"));
+        };
+
+        if rtype.dimensions > 0 {
+            return Ok(Some("array"));
+        }
+
+        if stack.contains(qualified_name) {
+            return Err(Diagnostic::new(
+                rtype.span,
+                format!(
+                    "Identifier {} refers to itself: {} -> {}",
+                    stack[0],
+                    stack.join(" -> "),
+                    qualified_name
+                ),
+            ));
+        }
+
+        if built_in_type(&rtype.name.name).is_none()
+            && !declaring_file.scope.contains_key(&rtype.name.name)
+        {
+            // Unknown types are reported when compiling the declaration
+            return Ok(None);
+        }
+
+        stack.push(qualified_name.clone());
+        let schema_type = self.underlying_type(declaring_file, &rtype.name.name, stack);
+        stack.pop();
+
+        schema_type
+    }
+
+    fn compile_type_ref(
+        &self,
+        file: &SourceFile,
+        rtype: &TypeRef,
+        annotations: &[Annotation],
+        is_declaration: bool,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<Map<String, Value>> {
+        let name = &rtype.name.name;
+
+        let mut schema = Map::default();
+
+        let schema_type = if let Some(schema_type) = built_in_type(name) {
+            if let Some(schema_type) = schema_type {
+                schema.insert("type".to_string(), json!(schema_type));
+            }
+
+            schema_type
+        } else if let Some(qualified_name) = file.scope.get(name) {
+            let schema_type = self
+                .underlying_type(file, name, &mut Vec::default())
+                .unwrap_or_default();
+
+            // Keywords next to `$ref` are ignored, so the reference is wrapped when the schema
+            // is extended. The type is repeated to keep it visible without resolving the
+            // reference.
+            if is_declaration || (rtype.dimensions == 0 && !annotations.is_empty()) {
+                schema.insert("allOf".to_string(), json!([{ "$ref": qualified_name }]));
+
+                if let Some(schema_type) = schema_type {
+                    schema.insert("type".to_string(), json!(schema_type));
+                }
+            } else {
+                schema.insert("$ref".to_string(), json!(qualified_name));
+            }
+
+            schema_type
+        } else {
+            diagnostics.push(Diagnostic::new(
+                rtype.name.span,
+                format!("Unknown type '{}'", name),
+            ));
+            return None;
+        };
+
+        let schema_type = if rtype.dimensions > 0 {
+            for _ in 0..rtype.dimensions {
+                let mut array_schema = Map::default();
+                array_schema.insert("type".to_string(), json!("array"));
+                array_schema.insert("items".to_string(), Value::Object(schema));
+                schema = array_schema;
+            }
+
+            Some("array")
+        } else {
+            schema_type
+        };
+
+        for annotation in annotations {
+            if let Err(diagnostic) = Self::apply_annotation(annotation, schema_type, &mut schema) {
+                diagnostics.push(diagnostic);
+            }
+        }
+
+        Some(schema)
+    }
+
+    /// Maps the annotation to schema keywords. `@CustomType` has no JSON Schema equivalent and
+    /// is kept as the `x-customType` extension, keyed by language.
+    fn apply_annotation(
+        annotation: &Annotation,
+        schema_type: Option<&str>,
+        schema: &mut Map<String, Value>,
+    ) -> Result<(), Diagnostic> {
+        let name = annotation.name.name.as_str();
+
+        let (arguments, applicable_types): (&[&str], &[&str]) = match name {
+            "NotEmpty" => (&[], &["string", "array", "object"]),
+            "CustomType" => (&["lang", "type"], &[]),
+            "Min" | "Max" => (&["value"], &["number", "integer"]),
+            "MinLength" | "MaxLength" | "Pattern" | "Format" => (&["value"], &["string"]),
+            "MinItems" | "MaxItems" => (&["value"], &["array"]),
+            "Description" => (&["value"], &[]),
+            _ => {
+                return Err(Diagnostic::new(
+                    annotation.name.span,
+                    format!("Unknown annotation @{}", name),
+                ))
+            }
+        };
+
+        if !applicable_types.is_empty()
+            && !schema_type.is_some_and(|schema_type| applicable_types.contains(&schema_type))
+        {
+            return Err(Diagnostic::new(
+                annotation.span,
+                format!(
+                    "@{} cannot be applied to {}, only to {}",
+                    name,
+                    schema_type.unwrap_or("any"),
+                    applicable_types.join(", ")
+                ),
+            ));
+        }
+
+        for argument in annotation.arguments.iter() {
+            if !arguments.contains(&argument.name.name.as_str()) {
+                return Err(Diagnostic::new(
+                    argument.span,
+                    format!("@{} has no argument '{}'", name, argument.name.name),
+                ));
+            }
+        }
+
+        let mut values = Vec::default();
+
+        for argument_name in arguments {
+            let argument = annotation
+                .arguments
+                .iter()
+                .find(|argument| argument.name.name == *argument_name)
+                .ok_or_else(|| {
+                    Diagnostic::new(
+                        annotation.span,
+                        format!("@{} requires argument '{}'", name, argument_name),
+                    )
+                })?;
+
+            let value = match (&argument.value, name) {
+                (Literal::Number(number), "Min" | "Max") => json!(number),
+                (Literal::Number(number), "MinLength" | "MaxLength" | "MinItems" | "MaxItems")
+                    if number.is_u64() =>
+                {
+                    json!(number)
+                }
+                (Literal::String(value), "CustomType" | "Pattern" | "Format" | "Description") => {
+                    json!(value)
+                }
+                _ => {
+                    return Err(Diagnostic::new(
+                        argument.span,
+                        format!("Invalid value of @{} argument '{}'", name, argument_name),
+                    ))
+                }
+            };
+
+            values.push(value);
+        }
+
+        match name {
+            "NotEmpty" => {
+                let keyword = match schema_type {
+                    Some("string") => "minLength",
+                    Some("array") => "minItems",
+                    _ => "minProperties",
+                };
+
+                schema.insert(keyword.to_string(), json!(1));
+            }
+            "CustomType" => {
+                let lang = values[0].as_str().unwrap_or_default().to_string();
+
+                let custom_types = schema
+                    .entry("x-customType")
+                    .or_insert_with(|| Value::Object(Map::default()));
+
+                if let Some(custom_types) = custom_types.as_object_mut() {
+                    custom_types.insert(lang, values[1].clone());
+                }
+            }
+            _ => {
+                let keyword = match name {
+                    "Min" => "minimum",
+                    "Max" => "maximum",
+                    "MinLength" => "minLength",
+                    "MaxLength" => "maxLength",
+                    "MinItems" => "minItems",
+                    "MaxItems" => "maxItems",
+                    "Pattern" => "pattern",
+                    "Format" => "format",
+                    _ => "description",
+                };
+
+                schema.insert(keyword.to_string(), values[0].clone());
+            }
+        }
+
+        Ok(())
+    }
+}
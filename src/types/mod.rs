@@ -0,0 +1,112 @@
+use std::{fmt, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use log::error;
+use semver::Version;
+
+use crate::definition::schema_registry::SchemaRegistry;
+
+use self::{ast::Span, compiler::TypeCompiler};
+
+pub mod ast;
+pub mod compiler;
+pub mod parser;
+
+pub const TYPES_PATH: &str = "data/types";
+
+pub const TYPE_FILE_EXTENSION: &str = "ptype";
+
+/// Error message attached to a part of a type file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(span: Span, message: String) -> Self {
+        Diagnostic { span, message }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    source_line: String,
+    length: usize,
+}
+
+impl CompileError {
+    pub fn new(path: PathBuf, source: &str, diagnostic: Diagnostic) -> Self {
+        let start = diagnostic.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |index| start + index);
+
+        let line = source[..start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        let length = source[start..diagnostic.span.end.clamp(start, line_end)]
+            .chars()
+            .count();
+
+        CompileError {
+            path,
+            line,
+            column,
+            message: diagnostic.message,
+            source_line: source[line_start..line_end].to_string(),
+            length: length.max(1),
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}:{}:{}: {}",
+            self.path.display(),
+            self.line,
+            self.column,
+            self.message
+        )?;
+        writeln!(f, "    {}", self.source_line)?;
+        write!(
+            f,
+            "    {}{}",
+            " ".repeat(self.column - 1),
+            "^".repeat(self.length)
+        )
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Compiles every type file in the directory and adds the resulting schemas to the registry.
+pub fn load_types(schema_registry: &SchemaRegistry, directory: &str) -> Result<()> {
+    let compiled_types = TypeCompiler::compile_directory(directory).map_err(|errors| {
+        for err in errors.iter() {
+            error!("{}", err);
+        }
+
+        anyhow!(
+            "{} type compilation error(s) in {}",
+            errors.len(),
+            directory
+        )
+    })?;
+
+    for compiled_type in compiled_types {
+        schema_registry.add(
+            &compiled_type.name,
+            Version::new(1, 0, 0),
+            compiled_type.schema,
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,434 @@
+use super::{
+    ast::{
+        Annotation, AnnotationArgument, Declaration, DeclarationKind, Field, Ident, Import,
+        Literal, Module, Span, TypeRef,
+    },
+    Diagnostic,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    String(String),
+    Number(String),
+    Symbol(char),
+    Eof,
+}
+
+impl TokenKind {
+    fn describe(&self) -> String {
+        match self {
+            TokenKind::Ident(name) => format!("'{}'", name),
+            TokenKind::String(_) => "string".to_string(),
+            TokenKind::Number(number) => format!("number {}", number),
+            TokenKind::Symbol(symbol) => format!("'{}'", symbol),
+            TokenKind::Eof => "end of file".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+const SYMBOLS: &[char] = &['{', '}', '(', ')', '[', ']', ':', ';', ',', '@', '.'];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, Diagnostic> {
+    let mut tokens = Vec::default();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+
+        if c == '/' && chars.peek().is_some_and(|(_, next)| *next == '/') {
+            for (_, c) in chars.by_ref() {
+                if c == '\n' {
+                    break;
+                }
+            }
+            continue;
+        }
+
+        let kind = if c.is_alphabetic() || c == '_' {
+            let mut end = start + c.len_utf8();
+
+            while let Some((index, c)) = chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_') {
+                end = index + c.len_utf8();
+            }
+
+            TokenKind::Ident(source[start..end].to_string())
+        } else if c.is_ascii_digit() || c == '-' {
+            let mut end = start + 1;
+
+            while let Some((index, _)) =
+                chars.next_if(|(_, c)| c.is_ascii_digit() || *c == '.' || *c == 'e' || *c == 'E')
+            {
+                end = index + 1;
+            }
+
+            TokenKind::Number(source[start..end].to_string())
+        } else if c == '"' {
+            let mut value = String::default();
+            let mut terminated = false;
+
+            while let Some((_, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        terminated = true;
+                        break;
+                    }
+                    '\\' => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, 't')) => value.push('\t'),
+                        Some((_, c)) => value.push(c),
+                        None => break,
+                    },
+                    '\n' => break,
+                    c => value.push(c),
+                }
+            }
+
+            if !terminated {
+                return Err(Diagnostic::new(
+                    Span::new(start, start + 1),
+                    "Unterminated string".to_string(),
+                ));
+            }
+
+            TokenKind::String(value)
+        } else if SYMBOLS.contains(&c) {
+            TokenKind::Symbol(c)
+        } else {
+            return Err(Diagnostic::new(
+                Span::new(start, start + c.len_utf8()),
+                format!("Unexpected character '{}'", c),
+            ));
+        };
+
+        let end = chars
+            .peek()
+            .map(|(index, _)| *index)
+            .unwrap_or(source.len());
+
+        tokens.push(Token {
+            kind,
+            span: Span::new(start, end),
+        });
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::Eof,
+        span: Span::new(source.len(), source.len()),
+    });
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position]
+    }
+
+    fn peek_next(&self) -> &Token {
+        let position = (self.position + 1).min(self.tokens.len() - 1);
+        &self.tokens[position]
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.position].clone();
+
+        if token.kind != TokenKind::Eof {
+            self.position += 1;
+        }
+
+        token
+    }
+
+    fn unexpected(&self, expected: &str) -> Diagnostic {
+        let token = self.peek();
+
+        Diagnostic::new(
+            token.span,
+            format!("Expected {}, found {}", expected, token.kind.describe()),
+        )
+    }
+
+    fn is_symbol(&self, symbol: char) -> bool {
+        self.peek().kind == TokenKind::Symbol(symbol)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.peek().kind, TokenKind::Ident(name) if name == keyword)
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> Result<Span, Diagnostic> {
+        if self.is_symbol(symbol) {
+            Ok(self.advance().span)
+        } else {
+            Err(self.unexpected(&format!("'{}'", symbol)))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<Span, Diagnostic> {
+        if self.is_keyword(keyword) {
+            Ok(self.advance().span)
+        } else {
+            Err(self.unexpected(&format!("'{}'", keyword)))
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<Ident, Diagnostic> {
+        match self.peek().kind.clone() {
+            TokenKind::Ident(name) => Ok(Ident {
+                name,
+                span: self.advance().span,
+            }),
+            _ => Err(self.unexpected("identifier")),
+        }
+    }
+
+    fn parse_module(&mut self) -> Result<Module, Diagnostic> {
+        let mut module = Module::default();
+
+        while self.is_keyword("from") {
+            module.imports.push(self.parse_import()?);
+        }
+
+        while self.peek().kind != TokenKind::Eof {
+            module.declarations.push(self.parse_declaration()?);
+        }
+
+        Ok(module)
+    }
+
+    /// `from a.b.c import { A, B };`
+    fn parse_import(&mut self) -> Result<Import, Diagnostic> {
+        self.expect_keyword("from")?;
+
+        let first = self.expect_ident()?;
+        let mut namespace = first.name;
+        let mut span = first.span;
+
+        while self.is_symbol('.') {
+            self.advance();
+
+            let part = self.expect_ident()?;
+            namespace = format!("{}.{}", namespace, part.name);
+            span = span.to(part.span);
+        }
+
+        self.expect_keyword("import")?;
+        self.expect_symbol('{')?;
+
+        let mut names = vec![self.expect_ident()?];
+
+        while self.is_symbol(',') {
+            self.advance();
+
+            if self.is_symbol('}') {
+                break;
+            }
+
+            names.push(self.expect_ident()?);
+        }
+
+        self.expect_symbol('}')?;
+        self.expect_symbol(';')?;
+
+        Ok(Import {
+            namespace: Ident {
+                name: namespace,
+                span,
+            },
+            names,
+        })
+    }
+
+    fn parse_annotations(&mut self) -> Result<Vec<Annotation>, Diagnostic> {
+        let mut annotations = Vec::default();
+
+        while self.is_symbol('@') {
+            annotations.push(self.parse_annotation()?);
+        }
+
+        Ok(annotations)
+    }
+
+    /// `@Name` or `@Name(key: "value", ...)`
+    fn parse_annotation(&mut self) -> Result<Annotation, Diagnostic> {
+        let start = self.expect_symbol('@')?;
+        let name = self.expect_ident()?;
+        let mut span = start.to(name.span);
+        let mut arguments = Vec::default();
+
+        if self.is_symbol('(') {
+            self.advance();
+
+            while !self.is_symbol(')') {
+                arguments.push(self.parse_annotation_argument()?);
+
+                if !self.is_symbol(',') {
+                    break;
+                }
+
+                self.advance();
+            }
+
+            span = span.to(self.expect_symbol(')')?);
+        }
+
+        Ok(Annotation {
+            name,
+            arguments,
+            span,
+        })
+    }
+
+    fn parse_annotation_argument(&mut self) -> Result<AnnotationArgument, Diagnostic> {
+        let start = self.peek().span;
+
+        let name = if matches!(self.peek().kind, TokenKind::Ident(_))
+            && self.peek_next().kind == TokenKind::Symbol(':')
+        {
+            let name = self.expect_ident()?;
+            self.advance();
+            name
+        } else {
+            Ident {
+                name: "value".to_string(),
+                span: start,
+            }
+        };
+
+        let (value, end) = self.parse_literal()?;
+
+        Ok(AnnotationArgument {
+            name,
+            value,
+            span: start.to(end),
+        })
+    }
+
+    fn parse_literal(&mut self) -> Result<(Literal, Span), Diagnostic> {
+        let token = self.peek().clone();
+
+        let literal = match &token.kind {
+            TokenKind::String(value) => Literal::String(value.clone()),
+            TokenKind::Number(number) => {
+                let number = serde_json::from_str::<serde_json::Number>(number).map_err(|_| {
+                    Diagnostic::new(token.span, format!("Invalid number {}", number))
+                })?;
+
+                Literal::Number(number)
+            }
+            TokenKind::Ident(name) if name == "true" => Literal::Boolean(true),
+            TokenKind::Ident(name) if name == "false" => Literal::Boolean(false),
+            _ => return Err(self.unexpected("string, number or boolean")),
+        };
+
+        self.advance();
+
+        Ok((literal, token.span))
+    }
+
+    fn parse_declaration(&mut self) -> Result<Declaration, Diagnostic> {
+        let annotations = self.parse_annotations()?;
+
+        if self.is_keyword("identifier") {
+            self.advance();
+
+            let name = self.expect_ident()?;
+            self.expect_symbol(':')?;
+            let rtype = self.parse_type_ref()?;
+            self.expect_symbol(';')?;
+
+            Ok(Declaration {
+                name,
+                annotations,
+                kind: DeclarationKind::Identifier(rtype),
+            })
+        } else if self.is_keyword("type") {
+            self.advance();
+
+            let name = self.expect_ident()?;
+            self.expect_symbol('{')?;
+
+            let mut fields = Vec::default();
+
+            while !self.is_symbol('}') {
+                fields.push(self.parse_field()?);
+            }
+
+            self.advance();
+
+            Ok(Declaration {
+                name,
+                annotations,
+                kind: DeclarationKind::Type(fields),
+            })
+        } else {
+            Err(self.unexpected("'identifier' or 'type'"))
+        }
+    }
+
+    /// `@Annotation optional name: Type;`
+    fn parse_field(&mut self) -> Result<Field, Diagnostic> {
+        let annotations = self.parse_annotations()?;
+
+        let optional =
+            self.is_keyword("optional") && matches!(self.peek_next().kind, TokenKind::Ident(_));
+
+        if optional {
+            self.advance();
+        }
+
+        let name = self.expect_ident()?;
+        self.expect_symbol(':')?;
+        let rtype = self.parse_type_ref()?;
+        self.expect_symbol(';')?;
+
+        Ok(Field {
+            name,
+            optional,
+            annotations,
+            rtype,
+        })
+    }
+
+    /// `Type` or `Type[]`
+    fn parse_type_ref(&mut self) -> Result<TypeRef, Diagnostic> {
+        let name = self.expect_ident()?;
+        let mut span = name.span;
+        let mut dimensions = 0;
+
+        while self.is_symbol('[') {
+            self.advance();
+            span = span.to(self.expect_symbol(']')?);
+            dimensions += 1;
+        }
+
+        Ok(TypeRef {
+            name,
+            dimensions,
+            span,
+        })
+    }
+}
+
+pub fn parse(source: &str) -> Result<Module, Diagnostic> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+    };
+
+    parser.parse_module()
+}
@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+/// Parses a duration such as `500ms`, `10s` or `2m`, as used for timeouts, leases and
+/// retentions.
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let duration = duration.trim();
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+
+    let (amount, unit) = duration.split_at(split);
+    let amount = amount
+        .parse::<u64>()
+        .map_err(|_| format!("Invalid duration '{duration}'"))?;
+
+    let parsed = match unit {
+        "ms" => Duration::from_millis(amount),
+        "s" => Duration::from_secs(amount),
+        "m" => Duration::from_secs(amount.saturating_mul(60)),
+        _ => return Err(format!("Invalid duration '{duration}', use ms, s or m")),
+    };
+
+    if parsed.is_zero() {
+        return Err("Duration should be greater than zero".to_string());
+    }
+
+    Ok(parsed)
+}